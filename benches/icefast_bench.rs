@@ -9,6 +9,7 @@ extern crate bencher;
 use bencher::Bencher;
 
 #[path = "../src/icefast.rs"]
+#[allow(dead_code)]
 mod icefast;
 
 static KEY8: [u8; 8] = [0x51, 0xF3, 0x0F, 0x11, 0x04, 0x24, 0x6A, 0x00];
@@ -239,6 +240,418 @@ fn decrypt_16x10k_fast_par_level0_bench(bench: &mut Bencher) {
     });
 }
 
+fn encrypt_16x10k_fast_scalar_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    test_ice.set_kernel(icefast::Kernel::Scalar);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_16x10k_fast_avx2_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    if icefast::Kernel::Avx2.is_supported() {
+        test_ice.set_kernel(icefast::Kernel::Avx2);
+    }
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_scalar_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    test_ice.set_kernel(icefast::Kernel::Scalar);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn decrypt_16x10k_fast_avx2_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    if icefast::Kernel::Avx2.is_supported() {
+        test_ice.set_kernel(icefast::Kernel::Avx2);
+    }
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn encrypt_16x10k_fast_bitsliced_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    test_ice.set_kernel(icefast::Kernel::Bitsliced);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_bitsliced_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    test_ice.set_kernel(icefast::Kernel::Bitsliced);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn encrypt_16x10k_fast_n1_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_n::<1>(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_n1_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt_n::<1>(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn encrypt_16x10k_fast_n2_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_n::<2>(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_n2_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt_n::<2>(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn encrypt_16x10k_fast_n4_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_n::<4>(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_n4_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt_n::<4>(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn encrypt_16x10k_fast_n8_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_n::<8>(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_n8_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt_n::<8>(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn key_set_fast_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    let mut key = KEY8;
+    bench.iter(|| {
+        key[0] = key[0].wrapping_add(1);
+        test_ice.key_set(&key);
+    });
+}
+
+fn key_set_bitwise_level0_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    let mut key = KEY8;
+    bench.iter(|| {
+        key[0] = key[0].wrapping_add(1);
+        test_ice.key_set_bitwise(&key);
+    });
+}
+
+fn key_set_fast_level2_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(2, &KEY16);
+    let mut key = KEY16;
+    bench.iter(|| {
+        key[0] = key[0].wrapping_add(1);
+        test_ice.key_set(&key);
+    });
+}
+
+fn key_set_bitwise_level2_bench(bench: &mut Bencher) {
+    let mut test_ice = icefast::Ice::new(2, &KEY16);
+    let mut key = KEY16;
+    bench.iter(|| {
+        key[0] = key[0].wrapping_add(1);
+        test_ice.key_set_bitwise(&key);
+    });
+}
+
+fn encrypt_16x10k_fast_level2_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(2, &KEY16);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn decrypt_16x10k_fast_level2_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(2, &KEY16);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        test_ice.decrypt(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn encrypt_kernel_bytes(bench: &mut Bencher, kernel: icefast::Kernel, len: usize) {
+    let mut test_ice = icefast::Ice::new(0, &KEY8);
+    test_ice.set_kernel(kernel);
+    let datax = EXPECT_TEXT_16.repeat(len / 16).as_bytes().to_owned();
+    // Build the large tables outside of the timed loop
+    test_ice.encrypt(&mut datax.clone());
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_1k_fast_scalar_level0_bench(bench: &mut Bencher) {
+    encrypt_kernel_bytes(bench, icefast::Kernel::Scalar, 1 << 10);
+}
+
+fn encrypt_1k_fast_large_level0_bench(bench: &mut Bencher) {
+    encrypt_kernel_bytes(bench, icefast::Kernel::LargeTable, 1 << 10);
+}
+
+fn encrypt_64k_fast_scalar_level0_bench(bench: &mut Bencher) {
+    encrypt_kernel_bytes(bench, icefast::Kernel::Scalar, 64 << 10);
+}
+
+fn encrypt_64k_fast_large_level0_bench(bench: &mut Bencher) {
+    encrypt_kernel_bytes(bench, icefast::Kernel::LargeTable, 64 << 10);
+}
+
+fn encrypt_4m_fast_scalar_level0_bench(bench: &mut Bencher) {
+    encrypt_kernel_bytes(bench, icefast::Kernel::Scalar, 4 << 20);
+}
+
+fn encrypt_4m_fast_large_level0_bench(bench: &mut Bencher) {
+    encrypt_kernel_bytes(bench, icefast::Kernel::LargeTable, 4 << 20);
+}
+
+fn encrypt_32x10k_messages_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let messages = vec![EXPECT_TEXT_16.repeat(2).as_bytes().to_owned(); 10000];
+    bench.iter(|| {
+        let mut data = messages.clone();
+        for msg in data.iter_mut() {
+            test_ice.encrypt(msg);
+        }
+        assert_eq!(data.len(), messages.len());
+    });
+}
+
+fn encrypt_32x10k_many_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let messages = vec![EXPECT_TEXT_16.repeat(2).as_bytes().to_owned(); 10000];
+    bench.iter(|| {
+        let mut data = messages.clone();
+        let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|msg| msg.as_mut_slice()).collect();
+        test_ice.encrypt_many(&mut bufs);
+        assert_eq!(data.len(), messages.len());
+    });
+}
+
+fn encrypt_32x10k_many_par_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let messages = vec![EXPECT_TEXT_16.repeat(2).as_bytes().to_owned(); 10000];
+    bench.iter(|| {
+        let mut data = messages.clone();
+        let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|msg| msg.as_mut_slice()).collect();
+        test_ice.encrypt_many_par(&mut bufs);
+        assert_eq!(data.len(), messages.len());
+    });
+}
+
+fn encrypt_16x10k_vectored_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    // 1500 byte fragments, so most blocks straddle one
+    let mut data = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut bufs: Vec<std::io::IoSliceMut> = data.chunks_mut(1500).map(std::io::IoSliceMut::new).collect();
+        test_ice.encrypt_vectored(&mut bufs);
+        assert_eq!(bufs.len(), 107);
+    });
+}
+
+static THIN_DECRYPTOR: icefast::ThinIce = icefast::ThinIce::from_const_key(KEY8);
+
+fn decrypt_16x10k_thin_const_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let expect_text = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    let mut cipher_text = expect_text.clone();
+    test_ice.encrypt(&mut cipher_text);
+    bench.iter(|| {
+        let mut data = cipher_text.clone();
+        THIN_DECRYPTOR.decrypt(&mut data);
+        assert_eq!(data.len(), expect_text.len());
+    });
+}
+
+fn new_level0_bench(bench: &mut Bencher) {
+    bench.iter(|| icefast::Ice::new(0, &KEY8));
+}
+
+fn encrypt_16x10k_auto_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    icefast::Calibration::global();
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_auto(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_16_auto_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    icefast::Calibration::global();
+    let datax = EXPECT_TEXT_16.as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_auto(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_16x10k_fast_par_min_len_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let options = icefast::ParOptions::new().with_min_len(16 << 10);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_par_with(&mut data, &options);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_16_fast_par_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let datax = EXPECT_TEXT_16.as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_par(&mut data);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_16_fast_par_serial_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let options = icefast::ParOptions::new().with_serial_below(4096);
+    let datax = EXPECT_TEXT_16.as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_par_with(&mut data, &options);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_16x10k_fast_par_scoped_level0_bench(bench: &mut Bencher) {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let backend = std::sync::Arc::new(icefast::ScopedThreads::default());
+    let options = icefast::ParOptions::new().with_backend(backend);
+    let datax = EXPECT_TEXT_16.repeat(10000).as_bytes().to_owned();
+    bench.iter(|| {
+        let mut data = datax.clone();
+        test_ice.encrypt_par_with(&mut data, &options);
+        assert_eq!(data.len(), datax.len());
+    });
+}
+
+fn encrypt_8_new_per_key_x1k_level0_bench(bench: &mut Bencher) {
+    let keys: Vec<[u8; 8]> = (0..1000u64).map(|i| (i * 0x9e3779b97f4a7c15).to_be_bytes()).collect();
+    bench.iter(|| {
+        let cipher: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|key| {
+                let mut data = EXPECT_TEXT_8.as_bytes().to_owned();
+                icefast::Ice::new(0, key).encrypt(&mut data);
+                data
+            })
+            .collect();
+        assert_eq!(cipher.len(), keys.len());
+    });
+}
+
+fn encrypt_8_many_keys_x1k_level0_bench(bench: &mut Bencher) {
+    let keys: Vec<[u8; 8]> = (0..1000u64).map(|i| (i * 0x9e3779b97f4a7c15).to_be_bytes()).collect();
+    let block: [u8; 8] = EXPECT_TEXT_8.as_bytes().try_into().unwrap();
+    bench.iter(|| {
+        let cipher = icefast::encrypt_block_many_keys(0, &keys, &block);
+        assert_eq!(cipher.len(), keys.len());
+    });
+}
+
 benchmark_group!(
     bench_fast,
     encrypt_8_fast_level0_bench,
@@ -261,5 +674,45 @@ benchmark_group!(
     encrypt_16x10k_fast_par_level0_bench,
     decrypt_16x10k_fast_level0_bench,
     decrypt_16x10k_fast_par_level0_bench,
+    encrypt_16x10k_fast_scalar_level0_bench,
+    encrypt_16x10k_fast_avx2_level0_bench,
+    decrypt_16x10k_fast_scalar_level0_bench,
+    decrypt_16x10k_fast_avx2_level0_bench,
+    encrypt_16x10k_fast_bitsliced_level0_bench,
+    decrypt_16x10k_fast_bitsliced_level0_bench,
+    encrypt_16x10k_fast_n1_level0_bench,
+    decrypt_16x10k_fast_n1_level0_bench,
+    encrypt_16x10k_fast_n2_level0_bench,
+    decrypt_16x10k_fast_n2_level0_bench,
+    encrypt_16x10k_fast_n4_level0_bench,
+    decrypt_16x10k_fast_n4_level0_bench,
+    encrypt_16x10k_fast_n8_level0_bench,
+    decrypt_16x10k_fast_n8_level0_bench,
+    key_set_fast_level0_bench,
+    key_set_bitwise_level0_bench,
+    key_set_fast_level2_bench,
+    key_set_bitwise_level2_bench,
+    encrypt_16x10k_fast_level2_bench,
+    decrypt_16x10k_fast_level2_bench,
+    encrypt_1k_fast_scalar_level0_bench,
+    encrypt_1k_fast_large_level0_bench,
+    encrypt_64k_fast_scalar_level0_bench,
+    encrypt_64k_fast_large_level0_bench,
+    encrypt_4m_fast_scalar_level0_bench,
+    encrypt_4m_fast_large_level0_bench,
+    encrypt_32x10k_messages_level0_bench,
+    encrypt_32x10k_many_level0_bench,
+    encrypt_32x10k_many_par_level0_bench,
+    encrypt_16x10k_vectored_level0_bench,
+    decrypt_16x10k_thin_const_level0_bench,
+    new_level0_bench,
+    encrypt_16x10k_auto_level0_bench,
+    encrypt_16_auto_level0_bench,
+    encrypt_16x10k_fast_par_min_len_level0_bench,
+    encrypt_16_fast_par_level0_bench,
+    encrypt_16_fast_par_serial_level0_bench,
+    encrypt_16x10k_fast_par_scoped_level0_bench,
+    encrypt_8_new_per_key_x1k_level0_bench,
+    encrypt_8_many_keys_x1k_level0_bench,
 );
 benchmark_main!(bench_fast);
@@ -0,0 +1,233 @@
+// tokio counterparts of the `stream` adapters.
+//
+// Same buffering as `IceWriter`/`IceReader`, the plaintext or ciphertext
+// is gathered into runs for the kernels and the last block or two are
+// held back for the padding. What differs is that the inner side can
+// return `Pending` at any point, so everything that has been accepted is
+// kept in the adapter until the inner side takes it. The adapters own
+// their key, anything that borrows an `Ice` such as `Arc<Ice>`, so they
+// can be moved into a spawned task.
+use std::borrow::Borrow;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::icefast::Ice;
+use crate::mode::{Chain, Mode, Padding};
+
+/* Plaintext gathered before a run goes through the kernels */
+const RUN: usize = 16 * 1024;
+
+/// Encrypts everything written to it into the inner writer.
+///
+/// The async version of `stream::IceWriter`. `shutdown` takes the place of
+/// `finish`: it pads the held back bytes, writes them out and shuts the
+/// inner writer down. If padding fails the error comes back from every
+/// `shutdown` and the tail is never written.
+#[derive(Debug)]
+pub struct AsyncIceWriter<W: AsyncWrite + Unpin, K: Borrow<Ice>> {
+    inner: W,
+    ice: K,
+    chain: Chain,
+    padding: Padding,
+    pending: Vec<u8>,
+    // Ciphertext the inner writer hasn't taken yet
+    out: Vec<u8>,
+    written: usize,
+    total: u64,
+    finished: bool,
+}
+
+impl<W: AsyncWrite + Unpin, K: Borrow<Ice>> AsyncIceWriter<W, K> {
+    /// `ice` is the key, `&Ice` or an `Arc<Ice>` to share it with tasks.
+    pub fn new(inner: W, ice: K, mode: Mode, padding: Padding) -> Self {
+        AsyncIceWriter {
+            inner,
+            ice,
+            chain: Chain::new(mode),
+            padding,
+            pending: Vec::with_capacity(RUN + 16),
+            out: Vec::with_capacity(RUN + 16),
+            written: 0,
+            total: 0,
+            finished: false,
+        }
+    }
+
+    /// The inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// The inner writer, anything still buffered is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /*
+     * Encrypt everything that can't be affected by padding onto `out`.
+     */
+    fn seal(&mut self) {
+        let len = self.pending.len() - self.chain.hold_back(self.padding, self.pending.len());
+        self.chain.encrypt(self.ice.borrow(), &mut self.pending[..len]);
+        self.out.extend(self.pending.drain(..len));
+    }
+
+    /*
+     * Hand `out` to the inner writer until it has taken all of it.
+     */
+    fn poll_write_out(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.out.len() {
+            let len = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out[self.written..]))?;
+            if len == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += len;
+        }
+        self.out.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin, K: Borrow<Ice> + Unpin> AsyncWrite for AsyncIceWriter<W, K> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // Nothing new is taken while a run is still on its way out
+        ready!(this.poll_write_out(cx))?;
+
+        let len = buf.len().min(RUN);
+        this.pending.extend_from_slice(&buf[..len]);
+        this.total += len as u64;
+        if this.pending.len() >= RUN {
+            this.seal();
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    /// Writes out all but the held back bytes, see `AsyncIceWriter`.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.seal();
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.finished {
+            this.seal();
+            // Left in place on error, so a retry fails the same way
+            this.chain.encrypt_final(
+                this.ice.borrow(),
+                this.padding,
+                &this.pending,
+                this.total,
+                &mut this.out,
+            )?;
+            this.pending.clear();
+            this.finished = true;
+        }
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Decrypts everything read from the inner reader.
+///
+/// The async version of `stream::IceReader`, with the same lookahead and
+/// errors.
+#[derive(Debug)]
+pub struct AsyncIceReader<R: AsyncRead + Unpin, K: Borrow<Ice>> {
+    inner: R,
+    ice: K,
+    chain: Chain,
+    padding: Padding,
+    pending: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+    total: u64,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin, K: Borrow<Ice>> AsyncIceReader<R, K> {
+    /// `ice` is the key, see `AsyncIceWriter::new`.
+    pub fn new(inner: R, ice: K, mode: Mode, padding: Padding) -> Self {
+        AsyncIceReader {
+            inner,
+            ice,
+            chain: Chain::new(mode),
+            padding,
+            pending: Vec::with_capacity(RUN + 16),
+            plain: Vec::with_capacity(RUN + 16),
+            pos: 0,
+            total: 0,
+            eof: false,
+        }
+    }
+
+    /// The inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// The inner reader, anything already read from it but not returned is
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /*
+     * Same as `IceReader::fill`, except that a `Pending` read leaves
+     * everything as it was.
+     */
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let start = self.pending.len();
+        self.pending.resize(start + RUN, 0);
+        let mut buf = ReadBuf::new(&mut self.pending[start..]);
+        let polled = Pin::new(&mut self.inner).poll_read(cx, &mut buf);
+        let read = buf.filled().len();
+        self.pending.truncate(start + read);
+        ready!(polled)?;
+
+        self.plain.clear();
+        self.pos = 0;
+        self.total += read as u64;
+        if read == 0 {
+            self.eof = true;
+            let tail = std::mem::take(&mut self.pending);
+            self.chain
+                .decrypt_final(self.ice.borrow(), self.padding, &tail, self.total, &mut self.plain)?;
+        } else {
+            self.chain
+                .decrypt_ready(self.ice.borrow(), self.padding, &mut self.pending, &mut self.plain);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin, K: Borrow<Ice> + Unpin> AsyncRead for AsyncIceReader<R, K> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.pos == this.plain.len() {
+            if this.eof || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            ready!(this.poll_fill(cx))?;
+        }
+        let len = buf.remaining().min(this.plain.len() - this.pos);
+        buf.put_slice(&this.plain[this.pos..this.pos + len]);
+        this.pos += len;
+        Poll::Ready(Ok(()))
+    }
+}
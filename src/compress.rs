@@ -0,0 +1,141 @@
+// Optional compression stage in front of icefast::Ice.
+//
+// Compressing before encrypting makes the ciphertext length depend on the
+// content of the plaintext. That is fine for scripts and logs at rest but
+// leaks information for interactive data where an attacker can influence
+// part of the plaintext, so it is never applied implicitly.
+use std::io::Read;
+
+use crate::error::IceError;
+use crate::icefast::Ice;
+
+/*
+ * Header layout (24 bytes, encrypted along with the payload):
+ *   [0]      algorithm id
+ *   [1..8]   reserved, zero
+ *   [8..16]  original length, big endian
+ *   [16..24] compressed length, big endian
+ */
+const HEADER_LEN: usize = 24;
+
+#[cfg(feature = "deflate")]
+const ID_DEFLATE: u8 = 1;
+#[cfg(feature = "zstd")]
+const ID_ZSTD: u8 = 2;
+
+/// Compression algorithm applied by `compress_and_encrypt`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    #[cfg(feature = "deflate")]
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => ID_DEFLATE,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => ID_ZSTD,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, IceError> {
+        match id {
+            #[cfg(feature = "deflate")]
+            ID_DEFLATE => Ok(Compression::Deflate),
+            #[cfg(feature = "zstd")]
+            ID_ZSTD => Ok(Compression::Zstd),
+            _ => Err(IceError::UnsupportedCompression(id)),
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>, IceError> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                use std::io::Write;
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::stream::encode_all(data, 0)?),
+        }
+    }
+
+    fn decompress(self, data: &[u8], original_len: u64) -> Result<Vec<u8>, IceError> {
+        // Never inflate past the recorded length, a forged header is caught below.
+        let mut out = Vec::new();
+        match self {
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                flate2::read::DeflateDecoder::new(data)
+                    .take(original_len.saturating_add(1))
+                    .read_to_end(&mut out)?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::Decoder::new(data)?
+                    .take(original_len.saturating_add(1))
+                    .read_to_end(&mut out)?;
+            }
+        }
+        if out.len() as u64 != original_len {
+            return Err(IceError::BadHeader);
+        }
+        Ok(out)
+    }
+}
+
+/// Compress `data` with `compression`, then encrypt the header and payload.
+///
+/// The output is zero padded to a multiple of 8 bytes. Note that the output
+/// length reveals how well the plaintext compressed.
+pub fn compress_and_encrypt(
+    ice: &Ice,
+    data: &[u8],
+    compression: Compression,
+) -> Result<Vec<u8>, IceError> {
+    let payload = compression.compress(data)?;
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len() + 7);
+    out.push(compression.id());
+    out.extend_from_slice(&[0; 7]);
+    out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+    out.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    out.extend_from_slice(&payload);
+    out.resize(out.len().next_multiple_of(8), 0);
+
+    ice.encrypt(&mut out);
+    Ok(out)
+}
+
+/// Decrypt the output of `compress_and_encrypt` and return the original data.
+pub fn decrypt_and_decompress(ice: &Ice, data: &[u8]) -> Result<Vec<u8>, IceError> {
+    if !data.len().is_multiple_of(8) {
+        return Err(IceError::BlockSize(data.len()));
+    }
+    if data.len() < HEADER_LEN {
+        return Err(IceError::BadHeader);
+    }
+
+    let mut plain = data.to_owned();
+    ice.decrypt(&mut plain);
+
+    let (header, body) = plain.split_at(HEADER_LEN);
+    if header[1..8].iter().any(|&b| b != 0) {
+        return Err(IceError::BadHeader);
+    }
+    let original_len = u64::from_be_bytes(header[8..16].try_into().unwrap());
+    let compressed_len = u64::from_be_bytes(header[16..24].try_into().unwrap());
+    if compressed_len > body.len() as u64 || body.len() as u64 - compressed_len >= 8 {
+        return Err(IceError::BadHeader);
+    }
+
+    let compression = Compression::from_id(header[0])?;
+    compression.decompress(&body[..compressed_len as usize], original_len)
+}
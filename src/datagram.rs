@@ -0,0 +1,214 @@
+// Sealing single datagrams for unreliable transports like UDP.
+//
+// Packets can go missing, arrive twice or out of order, so each one has to
+// stand on its own: it carries its sequence number in the clear, is
+// encrypted in CTR mode with a nonce derived from that number, and ends in
+// a truncated tag over both. The opener checks the tag first and then a
+// sliding window of recently seen sequence numbers, so a captured packet
+// can't be played back.
+use std::borrow::Borrow;
+
+use crate::error::IceError;
+use crate::icefast::Ice;
+use crate::mode::{self, Mode, Padding};
+
+/*
+ * Datagram layout:
+ *   [0..8]    sequence number, big endian
+ *   [8..n-t]  ciphertext, as long as the payload
+ *   [n-t..n]  first t bytes of the tag over the nonce and the two above
+ */
+const SEQ_LEN: usize = 8;
+
+/// Default for `with_tag_len`, 4 bytes.
+pub const DEFAULT_TAG_LEN: usize = 4;
+
+/// Number of sequence numbers below the highest one still accepted.
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// Longest payload a datagram can carry, 512 KiB. Past that the keystream
+/// would run into the next sequence number's.
+pub const MAX_PAYLOAD_LEN: usize = 8 << 16;
+
+/*
+ * CTR nonce for a packet, the sequence number moved up 16 bits past the
+ * block counter, which leaves room for 512 KiB per packet.
+ */
+fn packet_mode(nonce: [u8; 8], seq: u64) -> Mode {
+    let nonce = u64::from_be_bytes(nonce).wrapping_add(seq << 16);
+    Mode::Ctr {
+        nonce: nonce.to_be_bytes(),
+    }
+}
+
+/*
+ * The tag covers the nonce as well, so a packet sealed for one direction
+ * can't be reflected back at its sender.
+ */
+fn packet_tag(mac: &Ice, nonce: [u8; 8], packet: &[u8]) -> [u8; 8] {
+    mode::mac(mac, &[&nonce[..], packet].concat())
+}
+
+/// Seals outgoing datagrams, see `DatagramOpener` for the other end.
+///
+/// `nonce` is the starting value the per-packet nonces are derived from.
+/// The two directions of a connection must use different ones, or
+/// different keys, since both count from sequence number 0.
+///
+/// The keys can be `&Ice` or an `Arc<Ice>`, like `FrameCodec`. There is no
+/// `Clone`: a copy would seal under the same sequence numbers again, and
+/// so reuse their keystream.
+#[derive(Debug)]
+pub struct DatagramSealer<K: Borrow<Ice>> {
+    ice: K,
+    mac: K,
+    nonce: [u8; 8],
+    tag_len: usize,
+    seq: u64,
+}
+
+impl<K: Borrow<Ice>> DatagramSealer<K> {
+    /// `mac` has to be a different key than `ice`.
+    pub fn new(ice: K, mac: K, nonce: [u8; 8]) -> Self {
+        DatagramSealer {
+            ice,
+            mac,
+            nonce,
+            tag_len: DEFAULT_TAG_LEN,
+            seq: 0,
+        }
+    }
+
+    /// Bytes of the 8 byte tag kept, from 4 to 8.
+    pub fn with_tag_len(mut self, bytes: usize) -> Self {
+        self.tag_len = bytes.clamp(4, 8);
+        self
+    }
+
+    /// Sequence number of the next datagram.
+    pub fn sequence(&self) -> u64 {
+        self.seq
+    }
+
+    /// Seal `payload` as the next datagram, 8 bytes of sequence number and
+    /// the tag longer than the payload.
+    ///
+    /// Fails with `IceError::FrameTooLarge` for payloads over
+    /// `MAX_PAYLOAD_LEN`, without using up a sequence number.
+    pub fn seal(&mut self, payload: &[u8]) -> Result<Vec<u8>, IceError> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(IceError::FrameTooLarge(payload.len()));
+        }
+        let seq = self.seq;
+        self.seq += 1;
+
+        let mode = packet_mode(self.nonce, seq);
+        let cipher = mode::encrypt(self.ice.borrow(), mode, Padding::None, payload)
+            .expect("CTR takes any length");
+        let mut packet = Vec::with_capacity(SEQ_LEN + cipher.len() + self.tag_len);
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&cipher);
+        let tag = packet_tag(self.mac.borrow(), self.nonce, &packet);
+        packet.extend_from_slice(&tag[..self.tag_len]);
+        Ok(packet)
+    }
+}
+
+/// Opens datagrams from a `DatagramSealer` with the same keys, nonce and
+/// tag length, dropping forgeries and replays.
+///
+/// Packets may arrive in any order as long as they are no more than
+/// `REPLAY_WINDOW` behind the newest one seen. Not `Clone` either, two
+/// copies of the window would each accept the same packet once.
+#[derive(Debug)]
+pub struct DatagramOpener<K: Borrow<Ice>> {
+    ice: K,
+    mac: K,
+    nonce: [u8; 8],
+    tag_len: usize,
+    // Newest sequence number seen, and a bit for each of the ones below it
+    // with bit 0 for the newest itself
+    highest: Option<u64>,
+    seen: u64,
+}
+
+impl<K: Borrow<Ice>> DatagramOpener<K> {
+    pub fn new(ice: K, mac: K, nonce: [u8; 8]) -> Self {
+        DatagramOpener {
+            ice,
+            mac,
+            nonce,
+            tag_len: DEFAULT_TAG_LEN,
+            highest: None,
+            seen: 0,
+        }
+    }
+
+    /// Same as `DatagramSealer::with_tag_len`.
+    pub fn with_tag_len(mut self, bytes: usize) -> Self {
+        self.tag_len = bytes.clamp(4, 8);
+        self
+    }
+
+    /// Check and decrypt `packet`, returning its sequence number and
+    /// payload.
+    ///
+    /// Fails with `IceError::BadHeader` when it is too short to be a
+    /// datagram, `IceError::BadMac` when the tag doesn't match and
+    /// `IceError::Replayed` when it was seen before or is too old to tell.
+    /// Only packets that pass move the window along.
+    pub fn open(&mut self, packet: &[u8]) -> Result<(u64, Vec<u8>), IceError> {
+        if packet.len() < SEQ_LEN + self.tag_len {
+            return Err(IceError::BadHeader);
+        }
+        let (body, tag) = packet.split_at(packet.len() - self.tag_len);
+        let expect = packet_tag(self.mac.borrow(), self.nonce, body);
+        if !mode::mac_eq(&expect[..self.tag_len], tag) {
+            return Err(IceError::BadMac);
+        }
+
+        let (seq, cipher) = body.split_at(SEQ_LEN);
+        let seq = u64::from_be_bytes(seq.try_into().unwrap());
+        if !self.fresh(seq) {
+            return Err(IceError::Replayed(seq));
+        }
+        let payload = mode::decrypt(
+            self.ice.borrow(),
+            packet_mode(self.nonce, seq),
+            Padding::None,
+            cipher,
+        )?;
+        self.accept(seq);
+        Ok((seq, payload))
+    }
+
+    fn fresh(&self, seq: u64) -> bool {
+        match self.highest {
+            None => true,
+            Some(highest) if seq > highest => true,
+            Some(highest) => {
+                let behind = highest - seq;
+                behind < REPLAY_WINDOW && self.seen & (1 << behind) == 0
+            }
+        }
+    }
+
+    fn accept(&mut self, seq: u64) {
+        match self.highest {
+            Some(highest) if seq <= highest => self.seen |= 1 << (highest - seq),
+            Some(highest) => {
+                let ahead = seq - highest;
+                self.seen = if ahead < REPLAY_WINDOW {
+                    self.seen << ahead | 1
+                } else {
+                    1
+                };
+                self.highest = Some(seq);
+            }
+            None => {
+                self.seen = 1;
+                self.highest = Some(seq);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Errors surfaced by the fallible ICE helpers.
+#[derive(Debug)]
+pub enum IceError {
+    /// The data is not a multiple of the 8 byte block size.
+    BlockSize(usize),
+    /// The header in front of the payload is missing or inconsistent.
+    BadHeader,
+    /// The payload was compressed with an algorithm this build doesn't support.
+    UnsupportedCompression(u8),
+    /// The padding found after decryption is not valid PKCS#7, usually a
+    /// wrong key or IV, or damaged data.
+    BadPadding,
+    /// A frame announced a body longer than the configured maximum.
+    FrameTooLarge(usize),
+    /// The message authentication tag didn't match, the data was altered or
+    /// the key is wrong.
+    BadMac,
+    /// A datagram with this sequence number was already opened, or is too
+    /// far behind the newest one to tell.
+    Replayed(u64),
+    /// A patch in a chained mode changes every ciphertext byte in the range
+    /// after it, which has to be re-encrypted as a whole.
+    Chained(Range<u64>),
+    /// An interrupted in-place operation left this journal behind, it has
+    /// to be recovered before the file is touched again.
+    Journal(PathBuf),
+    /// An underlying reader, writer or codec failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for IceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IceError::BlockSize(len) => {
+                write!(f, "data length {} is not a multiple of 8 bytes", len)
+            }
+            IceError::BadHeader => write!(f, "missing or malformed header"),
+            IceError::UnsupportedCompression(id) => {
+                write!(f, "unsupported compression algorithm id {}", id)
+            }
+            IceError::BadPadding => write!(f, "invalid padding"),
+            IceError::FrameTooLarge(len) => {
+                write!(f, "frame of {} bytes exceeds the maximum frame length", len)
+            }
+            IceError::BadMac => write!(f, "message authentication failed"),
+            IceError::Replayed(seq) => {
+                write!(f, "datagram {} replayed or outside the replay window", seq)
+            }
+            IceError::Chained(range) => write!(
+                f,
+                "chained mode, ciphertext bytes {}..{} must be re-encrypted",
+                range.start, range.end
+            ),
+            IceError::Journal(path) => write!(
+                f,
+                "interrupted in-place operation, recover from {} first",
+                path.display()
+            ),
+            IceError::Io(err) => write!(f, "i/o error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for IceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IceError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IceError {
+    fn from(err: io::Error) -> Self {
+        IceError::Io(err)
+    }
+}
+
+impl From<IceError> for io::Error {
+    /// I/O errors come back as they were, the rest as `InvalidData` with the
+    /// `IceError` inside.
+    fn from(err: IceError) -> Self {
+        match err {
+            IceError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
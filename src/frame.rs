@@ -0,0 +1,256 @@
+// Length delimited, encrypted messages over a byte stream.
+//
+// Each message goes out as its own frame with a fresh IV or nonce, so the
+// receiver can decode any frame on its own and a damaged frame never
+// spills into the next one. `FrameCodec` does the framing on buffers and
+// over std::io, and with the `codec` feature as a tokio-util codec.
+use std::borrow::Borrow;
+use std::io::{self, Read, Write};
+
+use crate::error::IceError;
+use crate::icefast::Ice;
+use crate::mode::{self, Mode, Padding};
+
+/*
+ * Frame layout:
+ *   [0..4]  body length, big endian
+ *   body:   [8 byte IV or nonce, CBC and CTR only]
+ *           [ciphertext]
+ *           [8 byte tag over IV and ciphertext, with a MAC key only]
+ */
+const LEN_PREFIX: usize = 4;
+
+/// Default for `FrameCodec::with_max_frame_len`, 8 MiB.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 8 << 20;
+
+/// Frames messages as a length prefix and ICE ciphertext.
+///
+/// The `iv` or `nonce` in the mode is a per-connection starting value. CBC
+/// frames use the encryption of it XORed with the frame number as their
+/// IV, CTR frames the frame number in its upper 32 bits as their nonce,
+/// leaving 2^32 blocks per frame. Either way it is sent along in the frame,
+/// so both ends only have to agree on the keys, the mode and the padding.
+/// A key that is reused across connections needs a different starting
+/// value on each of them.
+///
+/// The keys can be `&Ice` or anything else that borrows one. With an
+/// `Arc<Ice>` the codec owns its keys, so a `Framed` stream built on it
+/// can be moved into a spawned task.
+///
+/// There is no `Clone`: a copy would go on from the same frame number and
+/// send the same IVs and nonces again. Each end or direction gets a codec
+/// of its own.
+#[derive(Debug)]
+pub struct FrameCodec<K: Borrow<Ice>> {
+    ice: K,
+    mac: Option<K>,
+    mode: Mode,
+    padding: Padding,
+    max_frame_len: usize,
+    frames: u64,
+}
+
+impl<K: Borrow<Ice>> FrameCodec<K> {
+    pub fn new(ice: K, mode: Mode, padding: Padding) -> Self {
+        FrameCodec {
+            ice,
+            mac: None,
+            mode,
+            padding,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            frames: 0,
+        }
+    }
+
+    /// Append a tag to every frame, made and checked with `mac`. This has
+    /// to be a different key than the one encrypting.
+    pub fn with_mac(mut self, mac: K) -> Self {
+        self.mac = Some(mac);
+        self
+    }
+
+    /// Largest frame body accepted either way, IV and tag included.
+    pub fn with_max_frame_len(mut self, bytes: usize) -> Self {
+        self.max_frame_len = bytes;
+        self
+    }
+
+    fn iv_len(&self) -> usize {
+        match self.mode {
+            Mode::Ecb => 0,
+            _ => 8,
+        }
+    }
+
+    fn tag_len(&self) -> usize {
+        self.mac.as_ref().map_or(0, |_| 8)
+    }
+
+    /*
+     * The mode for the next frame and its IV or nonce.
+     */
+    fn next_mode(&mut self) -> Mode {
+        let frame = self.frames;
+        self.frames += 1;
+        match self.mode {
+            Mode::Ecb => Mode::Ecb,
+            Mode::Cbc { iv } => {
+                let mut iv = (u64::from_be_bytes(iv) ^ frame).to_be_bytes();
+                self.ice.borrow().encrypt(&mut iv);
+                Mode::Cbc { iv }
+            }
+            Mode::Ctr { nonce } => {
+                let nonce = u64::from_be_bytes(nonce).wrapping_add(frame << 32);
+                Mode::Ctr {
+                    nonce: nonce.to_be_bytes(),
+                }
+            }
+        }
+    }
+
+    /// Encrypt `msg` and append it to `dst` as one frame.
+    pub fn encode(&mut self, msg: &[u8], dst: &mut Vec<u8>) -> Result<(), IceError> {
+        let mode = self.next_mode();
+        let cipher = mode::encrypt(self.ice.borrow(), mode, self.padding, msg)?;
+        let body_len = self.iv_len() + cipher.len() + self.tag_len();
+        if body_len > self.max_frame_len || body_len > u32::MAX as usize {
+            return Err(IceError::FrameTooLarge(body_len));
+        }
+
+        dst.reserve(LEN_PREFIX + body_len);
+        dst.extend_from_slice(&(body_len as u32).to_be_bytes());
+        let body_start = dst.len();
+        match mode {
+            Mode::Ecb => {}
+            Mode::Cbc { iv } => dst.extend_from_slice(&iv),
+            Mode::Ctr { nonce } => dst.extend_from_slice(&nonce),
+        }
+        dst.extend_from_slice(&cipher);
+        if let Some(mac) = &self.mac {
+            let tag = mode::mac(mac.borrow(), &dst[body_start..]);
+            dst.extend_from_slice(&tag);
+        }
+        Ok(())
+    }
+
+    /// Decode the frame at the start of `src`, returning the message and the
+    /// number of bytes it took up, or `None` while the frame is incomplete.
+    ///
+    /// A frame announcing more than the maximum length fails straight away,
+    /// without waiting for its body.
+    pub fn decode(&self, src: &[u8]) -> Result<Option<(Vec<u8>, usize)>, IceError> {
+        let Some(body_len) = self.body_len(src)? else {
+            return Ok(None);
+        };
+        let Some(body) = src.get(LEN_PREFIX..LEN_PREFIX + body_len) else {
+            return Ok(None);
+        };
+        Ok(Some((self.open(body)?, LEN_PREFIX + body_len)))
+    }
+
+    /// Encrypt `msg` and write it to `writer` as one frame.
+    pub fn write_frame(&mut self, writer: &mut impl Write, msg: &[u8]) -> Result<(), IceError> {
+        let mut frame = Vec::new();
+        self.encode(msg, &mut frame)?;
+        writer.write_all(&frame)?;
+        Ok(())
+    }
+
+    /// Read the next frame from `reader` and decrypt it, `None` when the
+    /// reader ends cleanly between frames.
+    pub fn read_frame(&self, reader: &mut impl Read) -> Result<Option<Vec<u8>>, IceError> {
+        let mut prefix = [0; LEN_PREFIX];
+        let mut read = 0;
+        while read < LEN_PREFIX {
+            match reader.read(&mut prefix[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(len) => read += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let body_len = self.body_len(&prefix)?.unwrap();
+        let mut body = vec![0; body_len];
+        reader.read_exact(&mut body)?;
+        self.open(&body).map(Some)
+    }
+
+    /*
+     * The body length from the prefix at the start of `src`, once there.
+     */
+    fn body_len(&self, src: &[u8]) -> Result<Option<usize>, IceError> {
+        let Some(prefix) = src.first_chunk::<LEN_PREFIX>() else {
+            return Ok(None);
+        };
+        let body_len = u32::from_be_bytes(*prefix) as usize;
+        if body_len > self.max_frame_len {
+            return Err(IceError::FrameTooLarge(body_len));
+        }
+        Ok(Some(body_len))
+    }
+
+    /*
+     * Check and decrypt a frame body.
+     */
+    fn open(&self, body: &[u8]) -> Result<Vec<u8>, IceError> {
+        let (iv_len, tag_len) = (self.iv_len(), self.tag_len());
+        if body.len() < iv_len + tag_len {
+            return Err(IceError::BadHeader);
+        }
+
+        let (body, tag) = body.split_at(body.len() - tag_len);
+        if let Some(mac) = &self.mac {
+            if !mode::mac_eq(&mode::mac(mac.borrow(), body), tag) {
+                return Err(IceError::BadMac);
+            }
+        }
+
+        let (iv, cipher) = body.split_at(iv_len);
+        let mode = match self.mode {
+            Mode::Ecb => Mode::Ecb,
+            Mode::Cbc { .. } => Mode::Cbc {
+                iv: iv.try_into().unwrap(),
+            },
+            Mode::Ctr { .. } => Mode::Ctr {
+                nonce: iv.try_into().unwrap(),
+            },
+        };
+        mode::decrypt(self.ice.borrow(), mode, self.padding, cipher)
+    }
+}
+
+#[cfg(feature = "codec")]
+impl<K: Borrow<Ice>> tokio_util::codec::Encoder<&[u8]> for FrameCodec<K> {
+    type Error = IceError;
+
+    fn encode(&mut self, msg: &[u8], dst: &mut bytes::BytesMut) -> Result<(), IceError> {
+        let mut frame = Vec::new();
+        FrameCodec::encode(self, msg, &mut frame)?;
+        dst.extend_from_slice(&frame);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "codec")]
+impl<K: Borrow<Ice>> tokio_util::codec::Decoder for FrameCodec<K> {
+    type Item = Vec<u8>;
+    type Error = IceError;
+
+    fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Vec<u8>>, IceError> {
+        match FrameCodec::decode(self, src)? {
+            Some((msg, len)) => {
+                let _ = src.split_to(len);
+                Ok(Some(msg))
+            }
+            None => {
+                // Make room for the rest of the frame in one go
+                if let Some(body_len) = self.body_len(src)? {
+                    src.reserve(LEN_PREFIX + body_len - src.len());
+                }
+                Ok(None)
+            }
+        }
+    }
+}
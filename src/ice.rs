@@ -336,7 +336,7 @@ impl Ice {
     #[inline(never)]
     pub fn ice_f(&self, p: u32, sk: &IceSubkey) -> u32 {
         /* Left half expansion */
-        let tl = ((p >> 16) & 0x3ff) | (((p >> 14) | (p << 18)) & 0xffc00);
+        let tl = ((p >> 16) & 0x3ff) | (p.rotate_right(14) & 0xffc00);
 
         /* Right half expansion */
         let tr = (p & 0x3ff) | ((p << 2) & 0xffc00);
@@ -407,8 +407,8 @@ impl Ice {
             | ptext[7] as u32;
 
         for i in (0..self.key.rounds).step_by(2) {
-            l ^= self.ice_f(r, &self.key.keysched[i as usize]);
-            r ^= self.ice_f(l, &self.key.keysched[i as usize + 1]);
+            l ^= self.ice_f(r, &self.key.keysched[i]);
+            r ^= self.ice_f(l, &self.key.keysched[i + 1]);
         }
 
         for i in 0..4 {
@@ -463,8 +463,8 @@ impl Ice {
             | ctext[7] as u32;
 
         for i in (0..self.key.rounds).rev().step_by(2) {
-            l ^= self.ice_f(r, &self.key.keysched[i as usize]);
-            r ^= self.ice_f(l, &self.key.keysched[i as usize - 1]);
+            l ^= self.ice_f(r, &self.key.keysched[i]);
+            r ^= self.ice_f(l, &self.key.keysched[i - 1]);
         }
 
         for i in 0..4 {
@@ -512,14 +512,14 @@ impl Ice {
     // }
     fn key_sched_build(&mut self, kb: &mut [u16; 4], n: i32, keyrot: &[i32]) {
         for (i, kr) in keyrot.iter().enumerate().take(8) {
-            let isk: &mut IceSubkey = &mut self.key.keysched[n as usize + i as usize];
+            let isk: &mut IceSubkey = &mut self.key.keysched[n as usize + i];
 
             for j in 0..3 {
-                (*isk).val[j] = 0;
+                isk.val[j] = 0;
             }
 
             for j in 0..15 {
-                let curr_sk: &mut u32 = &mut (*isk).val[j % 3];
+                let curr_sk: &mut u32 = &mut isk.val[j % 3];
 
                 for k in 0..4 {
                     let curr_kb = &mut kb[(kr + k) as usize & 3];
@@ -581,7 +581,7 @@ impl Ice {
 
             for j in 0..4 {
                 kb[3 - j] =
-                    (key[i * 8 + j * 2] as u16) << 8 | key[i as usize * 8 + j * 2 + 1] as u16;
+                    (key[i * 8 + j * 2] as u16) << 8 | key[i * 8 + j * 2 + 1] as u16;
             }
 
             self.key_sched_build(&mut kb, (i * 8).try_into().unwrap(), &KEYROT);
@@ -1,7 +1,13 @@
+#[cfg(feature = "rayon")]
 use rayon::prelude::*;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 /* Structure of a single round subkey */
-#[derive(Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct IceSubkey {
     val: [u32; 3],
 }
@@ -12,6 +18,540 @@ pub struct IceKeyStruct {
     size: usize,
     rounds: usize,
     pub keysched: Vec<IceSubkey>,
+    /* keysched reversed, so decryption walks its rounds forward too */
+    pub decsched: Vec<IceSubkey>,
+}
+
+/// Multi-block kernel used by `encrypt`/`decrypt` for bulk data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    /// `INTERLEAVE` blocks per call through `encrypt_n`, available everywhere.
+    Scalar,
+    /// Eight blocks per call using AVX2 gathers.
+    Avx2,
+    /// Sixteen blocks per call using AVX-512 gathers.
+    Avx512,
+    /// Sixty-four blocks per call evaluated as boolean circuits, without any
+    /// key or data dependent memory access. Never picked by `detect`.
+    Bitsliced,
+    /// `INTERLEAVE` blocks per call with the S-boxes merged pairwise into two
+    /// 2^20 entry tables (8 MiB, built once per process on first use), so a
+    /// round is two lookups instead of four. Only worth it with big caches
+    /// and big buffers, never picked by `detect`.
+    LargeTable,
+}
+
+impl Kernel {
+    /// The widest kernel supported by the running CPU.
+    pub fn detect() -> Self {
+        if Kernel::Avx512.is_supported() {
+            Kernel::Avx512
+        } else if Kernel::Avx2.is_supported() {
+            Kernel::Avx2
+        } else {
+            Kernel::Scalar
+        }
+    }
+
+    /// Whether the running CPU can execute this kernel.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Kernel::Scalar | Kernel::Bitsliced | Kernel::LargeTable => true,
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => is_x86_feature_detected!("avx512f"),
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => false,
+        }
+    }
+
+    /// Number of blocks processed per kernel call.
+    pub fn lanes(self) -> usize {
+        match self {
+            Kernel::Scalar | Kernel::LargeTable => INTERLEAVE,
+            Kernel::Avx2 => 8,
+            Kernel::Avx512 => 16,
+            Kernel::Bitsliced => 64,
+        }
+    }
+}
+
+/// Runs the independent chunk jobs behind the `_par` methods.
+pub trait ParBackend: fmt::Debug + Send + Sync {
+    /// Call `f` on every `chunk_len` byte chunk of `data` (the last one may
+    /// be shorter), in any order and possibly concurrently.
+    fn for_each_chunk(&self, data: &mut [u8], chunk_len: usize, f: &(dyn Fn(&mut [u8]) + Sync));
+}
+
+/// Runs every chunk on the calling thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Serial;
+
+impl ParBackend for Serial {
+    fn for_each_chunk(&self, data: &mut [u8], chunk_len: usize, f: &(dyn Fn(&mut [u8]) + Sync)) {
+        data.chunks_mut(chunk_len).for_each(f);
+    }
+}
+
+/// Splits the data into one run of chunks per worker and runs them on
+/// `std::thread::scope` threads, the calling thread taking the first run.
+#[derive(Clone, Copy, Debug)]
+pub struct ScopedThreads {
+    workers: usize,
+}
+
+impl ScopedThreads {
+    /// Use `workers` threads, including the calling one.
+    pub fn new(workers: usize) -> Self {
+        ScopedThreads {
+            workers: workers.max(1),
+        }
+    }
+}
+
+impl Default for ScopedThreads {
+    /// One worker per available CPU.
+    fn default() -> Self {
+        Self::new(std::thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+impl ParBackend for ScopedThreads {
+    fn for_each_chunk(&self, data: &mut [u8], chunk_len: usize, f: &(dyn Fn(&mut [u8]) + Sync)) {
+        let run_len = data.len().div_ceil(chunk_len).div_ceil(self.workers) * chunk_len;
+        if run_len == 0 {
+            return;
+        }
+
+        std::thread::scope(|scope| {
+            let mut runs = data.chunks_mut(run_len);
+            let first = runs.next();
+            for run in runs {
+                scope.spawn(move || run.chunks_mut(chunk_len).for_each(f));
+            }
+            if let Some(run) = first {
+                run.chunks_mut(chunk_len).for_each(f);
+            }
+        });
+    }
+}
+
+/// Hands the chunks to rayon, on the global pool or an injected one.
+#[cfg(feature = "rayon")]
+#[derive(Clone, Debug, Default)]
+pub struct Rayon {
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+#[cfg(feature = "rayon")]
+impl Rayon {
+    /// Run on `pool` instead of the global rayon pool.
+    pub fn with_pool(pool: Arc<rayon::ThreadPool>) -> Self {
+        Rayon { pool: Some(pool) }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl ParBackend for Rayon {
+    fn for_each_chunk(&self, data: &mut [u8], chunk_len: usize, f: &(dyn Fn(&mut [u8]) + Sync)) {
+        match &self.pool {
+            Some(pool) => pool.install(|| data.par_chunks_mut(chunk_len).for_each(f)),
+            None => data.par_chunks_mut(chunk_len).for_each(f),
+        }
+    }
+}
+
+/*
+ * Backend used when ParOptions doesn't name one: rayon's global pool when
+ * built with the rayon feature, scoped threads otherwise.
+ */
+fn default_backend() -> Arc<dyn ParBackend> {
+    static DEFAULT: OnceLock<Arc<dyn ParBackend>> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| {
+            #[cfg(feature = "rayon")]
+            let backend: Arc<dyn ParBackend> = Arc::new(Rayon::default());
+            #[cfg(not(feature = "rayon"))]
+            let backend: Arc<dyn ParBackend> = Arc::new(ScopedThreads::default());
+            backend
+        })
+        .clone()
+}
+
+/// Tuning for `encrypt_par_with`/`decrypt_par_with`. The default matches
+/// `encrypt_par`: kernel sized chunks on the default backend, which is
+/// rayon's global pool with the `rayon` feature and `ScopedThreads` without.
+#[derive(Clone, Debug, Default)]
+pub struct ParOptions {
+    min_len: usize,
+    serial_below: usize,
+    backend: Option<Arc<dyn ParBackend>>,
+}
+
+impl ParOptions {
+    /// Same as `ParOptions::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never split the data into chunks smaller than `bytes`.
+    pub fn with_min_len(mut self, bytes: usize) -> Self {
+        self.min_len = bytes;
+        self
+    }
+
+    /// Stay on the calling thread for buffers shorter than `bytes`.
+    pub fn with_serial_below(mut self, bytes: usize) -> Self {
+        self.serial_below = bytes;
+        self
+    }
+
+    /// Run the chunks on `backend`.
+    pub fn with_backend(mut self, backend: Arc<dyn ParBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Run on `pool` instead of the global rayon pool.
+    #[cfg(feature = "rayon")]
+    pub fn with_pool(self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.with_backend(Arc::new(Rayon::with_pool(pool)))
+    }
+}
+
+/// Stops an `encrypt_par_progress`/`decrypt_par_progress` call from another
+/// thread. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the calls watching this token to stop after their current segment.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Returned by `encrypt_par_progress`/`decrypt_par_progress` when they were
+/// cancelled. The first `processed` bytes are done and the rest untouched,
+/// so the call can be picked up again on `data[processed..]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled {
+    pub processed: usize,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled after {} bytes", self.processed)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Tuning for `encrypt_par_progress`/`decrypt_par_progress`.
+///
+/// The data is run one segment at a time through `encrypt_par_with` on the
+/// `ParOptions`, with the cancellation token checked before each segment.
+/// The workers add up the bytes they finish and call the progress callback,
+/// from whichever thread crossed the next step, with the running total.
+#[derive(Clone)]
+pub struct ProgressOptions {
+    par: ParOptions,
+    segment_len: usize,
+    step: usize,
+    progress: Option<Arc<dyn Fn(usize) + Send + Sync>>,
+    cancel: Option<CancelToken>,
+}
+
+impl Default for ProgressOptions {
+    /// 16 MiB segments, progress every MiB, on the default `ParOptions`.
+    fn default() -> Self {
+        ProgressOptions {
+            par: ParOptions::default(),
+            segment_len: 16 << 20,
+            step: 1 << 20,
+            progress: None,
+            cancel: None,
+        }
+    }
+}
+
+impl fmt::Debug for ProgressOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressOptions")
+            .field("par", &self.par)
+            .field("segment_len", &self.segment_len)
+            .field("step", &self.step)
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl ProgressOptions {
+    /// Same as `ProgressOptions::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How each segment is spread over threads.
+    pub fn with_par(mut self, par: ParOptions) -> Self {
+        self.par = par;
+        self
+    }
+
+    /// Bytes per segment, rounded up to a whole number of blocks. A
+    /// cancellation takes effect at the next segment boundary.
+    pub fn with_segment_len(mut self, bytes: usize) -> Self {
+        self.segment_len = bytes.max(1).checked_next_multiple_of(8).unwrap_or(usize::MAX & !7);
+        self
+    }
+
+    /// Call `progress` with the total bytes done, each time it passes a
+    /// multiple of `with_step` and once more at the end.
+    pub fn with_progress(mut self, progress: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Bytes between progress calls.
+    pub fn with_step(mut self, bytes: usize) -> Self {
+        self.step = bytes.max(1);
+        self
+    }
+
+    /// Stop when `cancel` is cancelled.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+}
+
+/// Execution path for one `encrypt_auto`/`decrypt_auto` call, ordered by
+/// the buffer size they start to pay off at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Execution {
+    /// One block at a time.
+    Single,
+    /// `INTERLEAVE` blocks at a time with `Kernel::Scalar`.
+    Interleaved,
+    /// The calibrated SIMD kernel on the calling thread.
+    Simd,
+    /// The calibrated kernel spread across the default parallel backend.
+    Parallel,
+}
+
+/* Buffer sizes timed by Calibration::measure */
+const CALIBRATION_SIZES: [usize; 7] = [64, 256, 1 << 10, 4 << 10, 16 << 10, 64 << 10, 256 << 10];
+
+static CALIBRATION: OnceLock<Calibration> = OnceLock::new();
+
+/// Crossover sizes between the execution paths of `Ice::encrypt_auto`.
+///
+/// Measured once per process on first use unless one was `install`ed
+/// first, e.g. from a file written by `save` on an earlier run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// Kernel used by `Execution::Simd` and `Execution::Parallel`.
+    pub kernel: Kernel,
+    /// Smallest buffer in bytes taking `Execution::Interleaved`.
+    pub interleaved_min: usize,
+    /// Smallest buffer in bytes taking `Execution::Simd`, `usize::MAX` for never.
+    pub simd_min: usize,
+    /// Smallest buffer in bytes taking `Execution::Parallel`, `usize::MAX` for never.
+    pub parallel_min: usize,
+}
+
+impl Calibration {
+    /// The process-wide calibration, measured on first use.
+    pub fn global() -> &'static Calibration {
+        CALIBRATION.get_or_init(Calibration::measure)
+    }
+
+    /// Make this the process-wide calibration. Fails, handing it back, if
+    /// one has already been installed or measured. A kernel this CPU
+    /// doesn't support is replaced as in `load`.
+    pub fn install(self) -> Result<(), Calibration> {
+        CALIBRATION.set(self.supported())
+    }
+
+    /// Time every path on a range of buffer sizes with a Thin-ICE key.
+    ///
+    /// A path's crossover is the smallest size from which it or a later
+    /// path was fastest at every larger size, so the result is monotonic
+    /// even when neighbouring paths are too close to call.
+    pub fn measure() -> Self {
+        let ice = Ice::new(0, &[0; 8]);
+        let kernel = Kernel::detect();
+        let paths: Vec<Execution> = [
+            Execution::Single,
+            Execution::Interleaved,
+            Execution::Simd,
+            Execution::Parallel,
+        ]
+        .into_iter()
+        .filter(|&path| path != Execution::Simd || kernel != Kernel::Scalar)
+        .collect();
+
+        // Spin up the thread pool before it is timed
+        ice.crypt_on(Execution::Parallel, kernel, &ice.key.keysched, &mut [0; 4096]);
+
+        let fastest: Vec<Execution> = CALIBRATION_SIZES
+            .iter()
+            .map(|&len| {
+                let mut data = vec![0; len];
+                let reps = CALIBRATION_SIZES[CALIBRATION_SIZES.len() - 1] / len;
+                let mut time = |path| {
+                    // Best of three to keep scheduler noise out
+                    (0..3)
+                        .map(|_| {
+                            let start = Instant::now();
+                            for _ in 0..reps {
+                                ice.crypt_on(path, kernel, &ice.key.keysched, &mut data);
+                            }
+                            start.elapsed()
+                        })
+                        .min()
+                        .unwrap_or(Duration::MAX)
+                };
+                paths.iter().copied().min_by_key(|&path| time(path)).unwrap()
+            })
+            .collect();
+
+        let min_len = |path| {
+            (0..fastest.len())
+                .find(|&i| fastest[i..].iter().all(|&f| f >= path))
+                .map_or(usize::MAX, |i| CALIBRATION_SIZES[i])
+        };
+
+        Calibration {
+            kernel,
+            interleaved_min: min_len(Execution::Interleaved),
+            simd_min: if kernel == Kernel::Scalar { usize::MAX } else { min_len(Execution::Simd) },
+            parallel_min: min_len(Execution::Parallel),
+        }
+    }
+
+    /// The path taken for a buffer of `len` bytes.
+    pub fn execution(&self, len: usize) -> Execution {
+        if len >= self.parallel_min {
+            Execution::Parallel
+        } else if len >= self.simd_min {
+            Execution::Simd
+        } else if len >= self.interleaved_min {
+            Execution::Interleaved
+        } else {
+            Execution::Single
+        }
+    }
+
+    /// Write the calibration as `key=value` lines.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> io::Result<()> {
+        std::fs::write(
+            path,
+            format!(
+                "kernel={:?}\ninterleaved_min={}\nsimd_min={}\nparallel_min={}\n",
+                self.kernel, self.interleaved_min, self.simd_min, self.parallel_min
+            ),
+        )
+    }
+
+    /// Read a calibration written by `save`. A kernel this CPU doesn't
+    /// support is replaced by `Kernel::Scalar` and never takes `Simd`.
+    pub fn load(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_owned());
+
+        let mut calibration = Calibration {
+            kernel: Kernel::Scalar,
+            interleaved_min: usize::MAX,
+            simd_min: usize::MAX,
+            parallel_min: usize::MAX,
+        };
+        for line in std::fs::read_to_string(path)?.lines() {
+            let (key, value) = line.split_once('=').ok_or_else(|| invalid(line))?;
+            let min = || value.trim().parse::<usize>().map_err(|_| invalid(line));
+            match key.trim() {
+                "kernel" => {
+                    calibration.kernel = match value.trim() {
+                        "Scalar" => Kernel::Scalar,
+                        "Avx2" => Kernel::Avx2,
+                        "Avx512" => Kernel::Avx512,
+                        "Bitsliced" => Kernel::Bitsliced,
+                        "LargeTable" => Kernel::LargeTable,
+                        _ => return Err(invalid(line)),
+                    }
+                }
+                "interleaved_min" => calibration.interleaved_min = min()?,
+                "simd_min" => calibration.simd_min = min()?,
+                "parallel_min" => calibration.parallel_min = min()?,
+                _ => return Err(invalid(line)),
+            }
+        }
+
+        Ok(calibration.supported())
+    }
+
+    /*
+     * Fall back to Kernel::Scalar, without the Simd path, when the CPU
+     * can't run the kernel. The calibration picks the kernel encrypt_auto
+     * runs, so an unsupported one must never get as far as the global.
+     */
+    fn supported(mut self) -> Self {
+        if !self.kernel.is_supported() {
+            self.kernel = Kernel::Scalar;
+            self.simd_min = usize::MAX;
+        }
+        self
+    }
+}
+
+/*
+ * S-box pairs merged into 20-bit lookups for Kernel::LargeTable:
+ * left[al] == sbox[0][al >> 10] | sbox[1][al & 0x3ff] and likewise right
+ * for ar with sbox[2] and sbox[3]. The S-boxes don't depend on the key so
+ * one copy serves every instance.
+ */
+struct LargeTables {
+    left: Box<[u32; 1 << 20]>,
+    right: Box<[u32; 1 << 20]>,
+}
+
+static LARGE_TABLES: OnceLock<LargeTables> = OnceLock::new();
+
+impl LargeTables {
+    fn new(sbox: &[[u32; 1024]; 4]) -> Self {
+        let merge = |hi: &[u32; 1024], lo: &[u32; 1024]| -> Box<[u32; 1 << 20]> {
+            (0..1 << 20)
+                .map(|i| hi[i >> 10] | lo[i & 0x3ff])
+                .collect::<Vec<u32>>()
+                .into_boxed_slice()
+                .try_into()
+                .unwrap()
+        };
+        LargeTables {
+            left: merge(&sbox[0], &sbox[1]),
+            right: merge(&sbox[2], &sbox[3]),
+        }
+    }
+}
+
+/* Round loop specialisation, picked once in `new` from the level */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unroll {
+    Thin,
+    Ice,
+    Ice2,
+    Loop,
 }
 
 #[warn(dead_code)]
@@ -24,6 +564,8 @@ pub struct Ice {
     sbox: [[u32; 1024]; 4],
     // static int		ice_sboxes_initialised = 0;
     sboxes_initialised: bool,
+    kernel: Kernel,
+    unroll: Unroll,
 }
 
 /* Modulo values for the S-boxes */
@@ -50,15 +592,129 @@ const ICE_PBOX: [u32; 32] = [
     0x00000002, 0x00000040, 0x00000800, 0x00001000, 0x00040000, 0x00100000, 0x02000000, 0x80000000,
 ];
 
+/* Widest profitable `encrypt_n` interleave, see the README */
+const INTERLEAVE: usize = 4;
+
+/* Bytes of small messages gathered per kernel call by `encrypt_many` */
+const MANY_BATCH: usize = 16 * 1024;
+
 /* The key rotation schedule */
 const KEYROT: [i32; 16] = [0, 1, 2, 3, 2, 1, 3, 0, 1, 3, 2, 0, 3, 1, 0, 2];
 
+/*
+ * Key bit spreading for key_sched_spread.
+ * Subkey word m takes bits m, m+3, .., m+12 of each of the four key words,
+ * bit m+3g landing at position 4*(4-g) before the per-word offset. All
+ * three 20-bit words are packed at bits 0, 20 and 40 of the result.
+ * Computed with shifts and masks rather than looked up by key byte, so the
+ * schedule doesn't touch memory at key dependent addresses either.
+ */
+const fn keysched_bits(w: u16) -> u64 {
+    let w = w as u64;
+    let mut spread = 0;
+    let mut m = 0;
+    while m < 3 {
+        let mut g = 0;
+        while g < 5 {
+            spread |= ((w >> (m + 3 * g)) & 1) << (4 * (4 - g) + 20 * m);
+            g += 1;
+        }
+        m += 1;
+    }
+    spread
+}
+
+/*
+ * Complement mask of a key word after s subkeys. One subkey leaves a word
+ * as w.rotate_left(1) ^ 0xfffe, so after s of them it is
+ * w.rotate_left(s) ^ KEYSCHED_FLIP[s].
+ */
+const KEYSCHED_FLIP: [u16; 9] = keysched_flip();
+
+const fn keysched_flip() -> [u16; 9] {
+    let mut flip = [0u16; 9];
+    let mut s = 1;
+    while s < 9 {
+        flip[s] = flip[s - 1].rotate_left(1) ^ 0xfffe;
+        s += 1;
+    }
+    flip
+}
+
+/*
+ * Fill `sched` with the schedule of `key` for an ICE of `size` key words,
+ * the closed form version of Ice::key_set_with. Writes straight into the
+ * caller's buffer so batches of keys don't allocate per key.
+ */
+fn key_sched_fill(key: &[u8], size: usize, sched: &mut [IceSubkey]) {
+    let words = |k: &[u8]| {
+        let mut kb = [0u16; 4];
+        for j in 0..4 {
+            kb[3 - j] = (k[j * 2] as u16) << 8 | k[j * 2 + 1] as u16;
+        }
+        kb
+    };
+
+    let rounds = sched.len();
+    if rounds == 8 {
+        key_sched_spread(&mut words(key), &KEYROT, sched);
+        return;
+    }
+
+    for i in 0..size {
+        let mut kb = words(&key[i * 8..]);
+        key_sched_spread(&mut kb, &KEYROT, &mut sched[i * 8..i * 8 + 8]);
+        key_sched_spread(&mut kb, &KEYROT[8..16], &mut sched[rounds - 8 - i * 8..rounds - i * 8]);
+    }
+}
+
+/// Encrypt one block under every key in `keys`, giving the same result as
+/// `Ice::new(level, key).encrypt` on each.
+///
+/// All keys share the compile time S-boxes and one reused schedule buffer,
+/// and keys are run two at a time so their rounds overlap like the blocks
+/// of `encrypt_n::<2>`.
+pub fn encrypt_block_many_keys<K: AsRef<[u8]>>(
+    level: usize,
+    keys: &[K],
+    block: &[u8; 8],
+) -> Vec<[u8; 8]> {
+    let (size, rounds) = if level < 1 { (1, 8) } else { (level, level * 16) };
+    let mut scheds = vec![IceSubkey { val: [0; 3] }; 2 * rounds];
+    let mut out = Vec::with_capacity(keys.len());
+
+    for pair in keys.chunks(2) {
+        let (sched0, sched1) = scheds.split_at_mut(rounds);
+        // An odd key out runs alongside itself
+        for (key, sched) in [pair[0].as_ref(), pair[pair.len() - 1].as_ref()]
+            .into_iter()
+            .zip([&mut *sched0, &mut *sched1])
+        {
+            assert!(key.len() == size * 8);
+            key_sched_fill(key, size, sched);
+        }
+
+        let mut blocks = [*block; 2];
+        let (mut l, mut r) = load_blocks::<2>(blocks.as_flattened());
+        for i in (0..rounds).step_by(2) {
+            l[0] ^= ice_f(&ICE_SBOX, r[0], &sched0[i]);
+            l[1] ^= ice_f(&ICE_SBOX, r[1], &sched1[i]);
+            r[0] ^= ice_f(&ICE_SBOX, l[0], &sched0[i + 1]);
+            r[1] ^= ice_f(&ICE_SBOX, l[1], &sched1[i + 1]);
+        }
+        store_blocks::<2>(blocks.as_flattened_mut(), &l, &r);
+
+        out.extend_from_slice(&blocks[..pair.len()]);
+    }
+    out
+}
+
 /*
  * Galois Field multiplication of a by b, modulo m.
  * Just like arithmetic multiplication, except that additions and
  * subtractions are replaced by XOR.
  */
-fn gf_mult(mut a: u32, mut b: u32, m: u32) -> u32 {
+const fn gf_mult(mut a: u32, mut b: u32, m: u32) -> u32 {
     let mut res: u32 = 0;
     while b != 0 {
         if b & 1 != 0 {
@@ -77,7 +733,7 @@ fn gf_mult(mut a: u32, mut b: u32, m: u32) -> u32 {
  * Galois Field exponentiation.
  * Raise the base to the power of 7, modulo m.
  */
-fn gf_exp7(b: u32, m: u32) -> u32 {
+const fn gf_exp7(b: u32, m: u32) -> u32 {
     if b == 0 {
         return 0;
     }
@@ -90,51 +746,160 @@ fn gf_exp7(b: u32, m: u32) -> u32 {
 /*
  * Carry out the ICE 32-bit P-box permutation.
  */
-fn ice_perm32(mut x: u32) -> u32 {
+const fn ice_perm32(mut x: u32) -> u32 {
     let mut res: u32 = 0;
-    let pbox = &ICE_PBOX;
-    for pb in pbox.iter().take(32) {
+    let mut i = 0;
+    while i < 32 {
         if x & 1 != 0 {
-            res |= pb;
+            res |= ICE_PBOX[i];
         }
         x >>= 1;
+        i += 1;
     }
     res
 }
 
+/*
+ * The ICE S-boxes, computed at compile time.
+ */
+static ICE_SBOX: [[u32; 1024]; 4] = sboxes_build();
+
+const fn sboxes_build() -> [[u32; 1024]; 4] {
+    let mut sbox = [[0; 1024]; 4];
+    let mut i = 0;
+    while i < 1024 {
+        let col = (i >> 1) & 0xff;
+        let row = (i & 0x1) | ((i & 0x200) >> 8);
+        let mut s = 0;
+        while s < 4 {
+            let x = gf_exp7((col ^ ICE_SXOR[s][row] as usize) as u32, ICE_SMOD[s][row] as u32);
+            sbox[s][i] = ice_perm32(x << (24 - 8 * s));
+            s += 1;
+        }
+        i += 1;
+    }
+    sbox
+}
+
+/*
+ * Closed form equivalent of Ice::key_sched_build.
+ * A subkey pulls 15 bits from each key word, all of which are still
+ * in their original positions, so the whole subkey is one spread per
+ * key word. The pulled bits re-enter at the top complemented, see
+ * KEYSCHED_FLIP, so the word states for each subkey are known up front
+ * and the 8 subkeys don't depend on each other.
+ * A const fn so that ThinIce::from_const_key can run it at compile time.
+ */
+const fn key_sched_spread(kb: &mut [u16; 4], keyrot: &[i32], out: &mut [IceSubkey]) {
+    let mut i = 0;
+    while i < 8 {
+        let mut spread = [0u64; 4];
+        let mut k = 0;
+        while k < 4 {
+            spread[k] = keysched_bits(kb[k].rotate_left(i as u32) ^ KEYSCHED_FLIP[i]);
+            k += 1;
+        }
+
+        // The per-word offsets stay below bit 20, so the fields never overlap
+        let mut packed = 0;
+        k = 0;
+        while k < 4 {
+            packed |= spread[(keyrot[i] as usize + k) & 3] << (3 - k);
+            k += 1;
+        }
+
+        out[i] = IceSubkey {
+            val: [
+                packed as u32 & 0xfffff,
+                (packed >> 20) as u32 & 0xfffff,
+                (packed >> 40) as u32 & 0xfffff,
+            ],
+        };
+        i += 1;
+    }
+
+    let mut k = 0;
+    while k < 4 {
+        kb[k] = kb[k].rotate_left(8) ^ KEYSCHED_FLIP[8];
+        k += 1;
+    }
+}
+
+/*
+ * Split N big endian blocks into their left and right halves.
+ */
+fn load_blocks<const N: usize>(chunk: &[u8]) -> ([u32; N], [u32; N]) {
+    let mut l = [0; N];
+    let mut r = [0; N];
+    chunk.chunks_exact(8).enumerate().for_each(|(i, block)| {
+        l[i] = u32::from_be_bytes(block[0..4].try_into().unwrap());
+        r[i] = u32::from_be_bytes(block[4..8].try_into().unwrap());
+    });
+    (l, r)
+}
+
+/*
+ * Write N blocks back with the final half swap.
+ */
+fn store_blocks<const N: usize>(chunk: &mut [u8], l: &[u32; N], r: &[u32; N]) {
+    chunk.chunks_exact_mut(8).enumerate().for_each(|(i, block)| {
+        block[0..4].copy_from_slice(&r[i].to_be_bytes()[..]);
+        block[4..8].copy_from_slice(&l[i].to_be_bytes()[..]);
+    });
+}
+
+/*
+ * Generate a fully unrolled kernel for a fixed round count.
+ * The subkeys come from a fixed size array indexed by literals, so there
+ * are no bounds checks or loop overhead left in the round function.
+ */
+macro_rules! unrolled_kernel {
+    ($name:ident, $rounds:literal, [$($i:literal),*]) => {
+        fn $name<const N: usize>(sbox: &[[u32; 1024]; 4], sched: &[IceSubkey], data: &mut [u8]) {
+            let ks: &[IceSubkey; $rounds] = sched.try_into().unwrap();
+
+            data.chunks_exact_mut(N * 8).for_each(|chunk| {
+                let (mut l, mut r) = load_blocks::<N>(chunk);
+                $(
+                    for b in 0..N {
+                        l[b] ^= ice_f(sbox, r[b], &ks[$i]);
+                    }
+                    for b in 0..N {
+                        r[b] ^= ice_f(sbox, l[b], &ks[$i + 1]);
+                    }
+                )*
+                store_blocks::<N>(chunk, &l, &r);
+            });
+        }
+    };
+}
+
+unrolled_kernel!(crypt_thin, 8, [0, 2, 4, 6]);
+unrolled_kernel!(crypt_ice, 16, [0, 2, 4, 6, 8, 10, 12, 14]);
+unrolled_kernel!(
+    crypt_ice2,
+    32,
+    [0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30]
+);
+
+/*
+ * The single round ICE f function.
+ */
+fn ice_f(sbox: &[[u32; 1024]; 4], p: u32, sk: &IceSubkey) -> u32 {
+    /* Expand, salt and split to sbox index values */
+    let (sb0, sb1, sb2, sb3) = Ice::ice_f_ess(p, sk);
+
+    /* S-box lookup and permutation */
+    sbox[0][sb0] | sbox[1][sb1] | sbox[2][sb2] | sbox[3][sb3]
+}
+
 impl Ice {
     /*
      * Initialise the ICE S-boxes.
-     * This only has to be done once.
+     * The tables themselves are built at compile time, see ICE_SBOX.
      */
     fn sboxes_init(&mut self) {
-        for i in 0..1024 {
-            let col = (i >> 1) & 0xff;
-            let row = (i & 0x1) | ((i & 0x200) >> 8);
-            let mut x = gf_exp7(
-                (col ^ ICE_SXOR[0][row] as usize).try_into().unwrap(),
-                ICE_SMOD[0][row].try_into().unwrap(),
-            ) << 24;
-            self.sbox[0][i] = ice_perm32(x);
-
-            x = gf_exp7(
-                (col ^ ICE_SXOR[1][row] as usize).try_into().unwrap(),
-                ICE_SMOD[1][row].try_into().unwrap(),
-            ) << 16;
-            self.sbox[1][i] = ice_perm32(x);
-
-            x = gf_exp7(
-                (col ^ ICE_SXOR[2][row] as usize).try_into().unwrap(),
-                ICE_SMOD[2][row].try_into().unwrap(),
-            ) << 8;
-            self.sbox[2][i] = ice_perm32(x);
-
-            x = gf_exp7(
-                (col ^ ICE_SXOR[3][row] as usize).try_into().unwrap(),
-                ICE_SMOD[3][row].try_into().unwrap(),
-            );
-            self.sbox[3][i] = ice_perm32(x);
-        }
+        self.sbox = ICE_SBOX;
     }
 
     /// Create a new ICE
@@ -147,9 +912,12 @@ impl Ice {
                 size: 0,
                 rounds: 0,
                 keysched: Vec::new(),
+                decsched: Vec::new(),
             },
             sbox: [[0; 1024]; 4],
             sboxes_initialised: false,
+            kernel: Kernel::detect(),
+            unroll: Unroll::Loop,
         };
 
         if !ik.sboxes_initialised {
@@ -168,15 +936,23 @@ impl Ice {
             assert!(key.len() == level * 8);
         }
 
+        ik.unroll = match ik.key.rounds {
+            8 => Unroll::Thin,
+            16 => Unroll::Ice,
+            32 => Unroll::Ice2,
+            _ => Unroll::Loop,
+        };
+
         ik.key.keysched = vec![IceSubkey { val: [0; 3] }; ik.key.rounds];
+        ik.key.decsched = ik.key.keysched.clone();
         ik.key_set(key);
         ik
     }
 
     /*
-     * The single round ICE f function.
+     * Expand and salt a half block into the two 20-bit S-box inputs.
      */
-    fn ice_f_ess(&self, p: u32, sk: &IceSubkey) -> (usize, usize, usize, usize) {
+    fn ice_f_salt(p: u32, sk: &IceSubkey) -> (u32, u32) {
         /* Expanded 2x20-bit values */
         let tr = p & 0x3ff | p << 2 & 0xffc00;
         let tl = p >> 16 & 0x3ff | p.rotate_left(18) & 0xffc00;
@@ -190,6 +966,15 @@ impl Ice {
         al ^= sk.val[0];
         ar ^= sk.val[1];
 
+        (al, ar)
+    }
+
+    /*
+     * Split the salted S-box inputs into the four 10-bit indices.
+     */
+    fn ice_f_ess(p: u32, sk: &IceSubkey) -> (usize, usize, usize, usize) {
+        let (al, ar) = Self::ice_f_salt(p, sk);
+
         (
             (al as usize >> 10) & 0x3ff,
             al as usize & 0x3ff,
@@ -199,151 +984,495 @@ impl Ice {
     }
 
     fn ice_f(&self, p: u32, sk: &IceSubkey) -> u32 {
-        /* Expand, salt and split to sbox index values */
-        let (sb0, sb1, sb2, sb3) = self.ice_f_ess(p, sk);
+        ice_f(&self.sbox, p, sk)
+    }
 
-        /* S-box lookup and permutation */
-        self.sbox[0][sb0] | self.sbox[1][sb1] | self.sbox[2][sb2] | self.sbox[3][sb3]
-    }
-
-    fn encrypt_16(&self, chunk: &mut [u8]) {
-        assert!(chunk.len() == 16);
-        
-        // compiler vectorizes with the writes to the chunk
-        let mut l1: u32 = u32::from_be_bytes(chunk[0..4].try_into().unwrap());
-        let mut r1: u32 = u32::from_be_bytes(chunk[4..8].try_into().unwrap());
-        let mut l2: u32 = u32::from_be_bytes(chunk[8..12].try_into().unwrap());
-        let mut r2: u32 = u32::from_be_bytes(chunk[12..16].try_into().unwrap());
-
-        // ice_f expansion and salting can be vectorized but the sbox
-        // lookup can't and without inline(never) the compiler will not
-        // vectorize the expansion and salting and ends up taking roughly
-        // the same time as the plain paired loop.
-        self.key.keysched.chunks_exact(2).for_each(|pair| {
-            l1 ^= self.ice_f(r1, &pair[0]);
-            l2 ^= self.ice_f(r2, &pair[0]);
-            r1 ^= self.ice_f(l1, &pair[1]);
-            r2 ^= self.ice_f(l2, &pair[1]);
-        });
+    /*
+     * ice_f with the merged S-box pairs of Kernel::LargeTable.
+     */
+    fn ice_f_large(tables: &LargeTables, p: u32, sk: &IceSubkey) -> u32 {
+        let (al, ar) = Self::ice_f_salt(p, sk);
 
-        chunk[0..4].copy_from_slice(&r1.to_be_bytes().as_slice());
-        chunk[4..8].copy_from_slice(&l1.to_be_bytes().as_slice());
-        chunk[8..12].copy_from_slice(&r2.to_be_bytes().as_slice());
-        chunk[12..16].copy_from_slice(&l2.to_be_bytes().as_slice());
+        tables.left[al as usize & 0xfffff] | tables.right[ar as usize & 0xfffff]
     }
 
-    fn encrypt_8(&self, chunk: &mut [u8]) {
-        let mut l: u32 = u32::from_be_bytes(chunk[0..4].try_into().unwrap());
-        let mut r: u32 = u32::from_be_bytes(chunk[4..8].try_into().unwrap());
+    /// Encrypt N independent blocks at a time, keeping N (l, r) pairs in
+    /// flight per round to hide the S-box lookup latency.
+    /// `data` must be a multiple of N blocks.
+    pub fn encrypt_n<const N: usize>(&self, data: &mut [u8]) {
+        self.crypt_n::<N>(&self.key.keysched, data);
+    }
 
-        self.key.keysched.chunks_exact(2).for_each(|pair| {
-            l ^= self.ice_f(r, &pair[0]);
-            r ^= self.ice_f(l, &pair[1]);
-        });
+    /// Decrypt N independent blocks at a time, see `encrypt_n`.
+    pub fn decrypt_n<const N: usize>(&self, data: &mut [u8]) {
+        self.crypt_n::<N>(&self.key.decsched, data);
+    }
 
-        chunk[0..4].copy_from_slice(&r.to_be_bytes()[..]);
-        chunk[4..8].copy_from_slice(&l.to_be_bytes()[..]);
+    /*
+     * Decryption is encryption with the reversed schedule, so every kernel
+     * below takes the schedule and walks it forward.
+     */
+    fn crypt_n<const N: usize>(&self, sched: &[IceSubkey], data: &mut [u8]) {
+        assert!(data.len().is_multiple_of(N * 8));
+
+        match self.unroll {
+            Unroll::Thin => crypt_thin::<N>(&self.sbox, sched, data),
+            Unroll::Ice => crypt_ice::<N>(&self.sbox, sched, data),
+            Unroll::Ice2 => crypt_ice2::<N>(&self.sbox, sched, data),
+            Unroll::Loop => self.crypt_loop::<N>(sched, data),
+        }
     }
 
-    /// Encrypt data in-place.
-    pub fn encrypt(&self, data: &mut [u8]) {
-        assert!(data.len() % 8 == 0, "Data must be a multiple of 8 bytes");
+    /*
+     * Generic round loop for levels above 2.
+     */
+    fn crypt_loop<const N: usize>(&self, sched: &[IceSubkey], data: &mut [u8]) {
+        data.chunks_exact_mut(N * 8).for_each(|chunk| {
+            // compiler vectorizes with the writes to the chunk
+            let (mut l, mut r) = load_blocks::<N>(chunk);
+
+            // ice_f expansion and salting can be vectorized but the sbox
+            // lookup can't and without inline(never) the compiler will not
+            // vectorize the expansion and salting and ends up taking roughly
+            // the same time as the plain paired loop.
+            sched.chunks_exact(2).for_each(|pair| {
+                for i in 0..N {
+                    l[i] ^= self.ice_f(r[i], &pair[0]);
+                }
+                for i in 0..N {
+                    r[i] ^= self.ice_f(l[i], &pair[1]);
+                }
+            });
 
-        data.chunks_exact_mut(16).for_each(|chunk| {
-            self.encrypt_16(chunk);
+            store_blocks::<N>(chunk, &l, &r);
         });
+    }
 
-        data.chunks_exact_mut(16)
-            .into_remainder()
-            .chunks_exact_mut(8)
-            .for_each(|chunk| {
-                self.encrypt_8(chunk);
+    fn crypt_large<const N: usize>(&self, sched: &[IceSubkey], data: &mut [u8]) {
+        assert!(data.len().is_multiple_of(N * 8));
+        let tables = LARGE_TABLES.get_or_init(|| LargeTables::new(&self.sbox));
+
+        data.chunks_exact_mut(N * 8).for_each(|chunk| {
+            let (mut l, mut r) = load_blocks::<N>(chunk);
+
+            sched.chunks_exact(2).for_each(|pair| {
+                for i in 0..N {
+                    l[i] ^= Self::ice_f_large(tables, r[i], &pair[0]);
+                }
+                for i in 0..N {
+                    r[i] ^= Self::ice_f_large(tables, l[i], &pair[1]);
+                }
             });
+
+            store_blocks::<N>(chunk, &l, &r);
+        });
+    }
+
+    /*
+     * Process fewer than 16 blocks left over by the wide kernels,
+     * one pass per set bit of the block count.
+     */
+    fn crypt_tail(&self, sched: &[IceSubkey], data: &mut [u8]) {
+        assert!(data.len() < 128);
+        let (c8, rest) = data.split_at_mut(data.len() & 64);
+        let (c4, rest) = rest.split_at_mut(rest.len() & 32);
+        let (c2, c1) = rest.split_at_mut(rest.len() & 16);
+        self.crypt_n::<8>(sched, c8);
+        self.crypt_n::<4>(sched, c4);
+        self.crypt_n::<2>(sched, c2);
+        self.crypt_n::<1>(sched, c1);
+    }
+
+    /// The multi-block kernel used for bulk data.
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    /// Override the kernel picked at construction, e.g. to compare them.
+    pub fn set_kernel(&mut self, kernel: Kernel) {
+        assert!(kernel.is_supported(), "{:?} is not supported by this CPU", kernel);
+        self.kernel = kernel;
+    }
+
+    /*
+     * Run the selected kernel over whole multiples of its width.
+     */
+    fn crypt_lanes(&self, kernel: Kernel, sched: &[IceSubkey], data: &mut [u8]) {
+        match kernel {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => data
+                .chunks_exact_mut(64)
+                .for_each(|chunk| unsafe { x86::crypt_avx2(self, sched, chunk) }),
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => data
+                .chunks_exact_mut(128)
+                .for_each(|chunk| unsafe { x86::crypt_avx512(self, sched, chunk) }),
+            Kernel::Bitsliced => data
+                .chunks_mut(bitslice::LANES * 8)
+                .for_each(|chunk| bitslice::crypt(sched, chunk)),
+            Kernel::LargeTable => self.crypt_large::<INTERLEAVE>(sched, data),
+            _ => self.crypt_n::<INTERLEAVE>(sched, data),
+        }
+    }
+
+    fn crypt(&self, sched: &[IceSubkey], data: &mut [u8]) {
+        self.crypt_with(self.kernel, sched, data);
+    }
+
+    fn crypt_with(&self, kernel: Kernel, sched: &[IceSubkey], data: &mut [u8]) {
+        assert!(data.len().is_multiple_of(8), "Data must be a multiple of 8 bytes");
+
+        if kernel == Kernel::Bitsliced {
+            // The table based tails would undo the point of the kernel.
+            self.crypt_lanes(kernel, sched, data);
+            return;
+        }
+
+        let width = kernel.lanes() * 8;
+        let (bulk, tail) = data.split_at_mut(data.len() / width * width);
+        self.crypt_lanes(kernel, sched, bulk);
+        self.crypt_tail(sched, tail);
+    }
+
+    fn crypt_par(&self, sched: &[IceSubkey], data: &mut [u8], options: &ParOptions) {
+        self.crypt_par_with(self.kernel, sched, data, options);
+    }
+
+    fn crypt_par_with(
+        &self,
+        kernel: Kernel,
+        sched: &[IceSubkey],
+        data: &mut [u8],
+        options: &ParOptions,
+    ) {
+        self.crypt_par_report(kernel, sched, data, options, &|_| {});
     }
 
-    fn decrypt_16(&self, chunk: &mut [u8]) {
-        // compiler vectorizes with the writes to the chunk
-        let mut l1: u32 = u32::from_be_bytes(chunk[0..4].try_into().unwrap());
-        let mut r1: u32 = u32::from_be_bytes(chunk[4..8].try_into().unwrap());
-        let mut l2: u32 = u32::from_be_bytes(chunk[8..12].try_into().unwrap());
-        let mut r2: u32 = u32::from_be_bytes(chunk[12..16].try_into().unwrap());
-
-        // ice_f_ess can be vectorized but the sbox lookup is not
-        // and without inline(never) the compiler will not vectorize
-        // and takes roughly the same time as the plain paired loop
-        self.key.keysched.rchunks_exact(2).for_each(|pair| {
-            l1 ^= self.ice_f(r1, &pair[1]);
-            l2 ^= self.ice_f(r2, &pair[1]);
-            r1 ^= self.ice_f(l1, &pair[0]);
-            r2 ^= self.ice_f(l2, &pair[0]);
+    /*
+     * crypt_par_with, calling `done` with the length of every chunk as soon
+     * as it is finished, on the thread that ran it.
+     */
+    fn crypt_par_report(
+        &self,
+        kernel: Kernel,
+        sched: &[IceSubkey],
+        data: &mut [u8],
+        options: &ParOptions,
+        done: &(dyn Fn(usize) + Sync),
+    ) {
+        assert!(data.len().is_multiple_of(8), "Data must be a multiple of 8 bytes");
+
+        if data.len() < options.serial_below {
+            self.crypt_with(kernel, sched, data);
+            done(data.len());
+            return;
+        }
+
+        // Whole kernel calls per chunk, only the last one runs a tail. A
+        // min_len past the data just means one chunk.
+        let width = kernel.lanes() * 8;
+        let chunk_len = options.min_len.min(data.len()).div_ceil(width).max(1) * width;
+        let backend = options.backend.clone().unwrap_or_else(default_backend);
+        backend.for_each_chunk(data, chunk_len, &|chunk| {
+            self.crypt_with(kernel, sched, chunk);
+            done(chunk.len());
         });
+    }
+
+    fn crypt_par_progress(
+        &self,
+        sched: &[IceSubkey],
+        data: &mut [u8],
+        options: &ProgressOptions,
+    ) -> Result<(), Cancelled> {
+        assert!(data.len().is_multiple_of(8), "Data must be a multiple of 8 bytes");
 
-        chunk[0..4].copy_from_slice(&r1.to_be_bytes()[..]);
-        chunk[4..8].copy_from_slice(&l1.to_be_bytes()[..]);
-        chunk[8..12].copy_from_slice(&r2.to_be_bytes()[..]);
-        chunk[12..16].copy_from_slice(&l2.to_be_bytes()[..]);
+        let total = AtomicUsize::new(0);
+        let done = |len: usize| {
+            let before = total.fetch_add(len, Ordering::Relaxed);
+            if let Some(progress) = &options.progress {
+                let after = before + len;
+                if after / options.step > before / options.step {
+                    progress(after);
+                }
+            }
+        };
+
+        let mut processed = 0;
+        for segment in data.chunks_mut(options.segment_len) {
+            if options.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                return Err(Cancelled { processed });
+            }
+            self.crypt_par_report(self.kernel, sched, segment, &options.par, &done);
+            processed += segment.len();
+        }
+
+        // The end rarely falls on a step, make sure it is seen
+        if let Some(progress) = &options.progress {
+            if !processed.is_multiple_of(options.step) {
+                progress(processed);
+            }
+        }
+        Ok(())
     }
 
-    fn decrypt_8(&self, chunk: &mut [u8]) {
-        let mut l: u32 = u32::from_be_bytes(chunk[0..4].try_into().unwrap());
-        let mut r: u32 = u32::from_be_bytes(chunk[4..8].try_into().unwrap());
+    fn crypt_on(&self, path: Execution, kernel: Kernel, sched: &[IceSubkey], data: &mut [u8]) {
+        match path {
+            Execution::Single => {
+                assert!(data.len().is_multiple_of(8), "Data must be a multiple of 8 bytes");
+                self.crypt_n::<1>(sched, data);
+            }
+            Execution::Interleaved => self.crypt_with(Kernel::Scalar, sched, data),
+            Execution::Simd => self.crypt_with(kernel, sched, data),
+            Execution::Parallel => {
+                self.crypt_par_with(kernel, sched, data, &ParOptions::default())
+            }
+        }
+    }
+
+    /// The path `encrypt_auto`/`decrypt_auto` take for a buffer of `len`
+    /// bytes, calibrating on first use. Doesn't apply to `Kernel::Bitsliced`
+    /// instances, see `encrypt_auto`.
+    pub fn auto_execution(&self, len: usize) -> Execution {
+        Calibration::global().execution(len)
+    }
 
-        self.key.keysched.rchunks_exact(2).for_each(|pair| {
-            l ^= self.ice_f(r, &pair[1]);
-            r ^= self.ice_f(l, &pair[0]);
+    /// Encrypt data in-place on whichever path the process-wide
+    /// `Calibration` found fastest for its length.
+    ///
+    /// An instance set to `Kernel::Bitsliced` ignores the calibration and
+    /// stays on its own kernel, since every other path indexes the S-box
+    /// tables by data.
+    pub fn encrypt_auto(&self, data: &mut [u8]) {
+        self.crypt_auto(&self.key.keysched, data);
+    }
+
+    /// Decrypt data in-place, see `encrypt_auto`.
+    pub fn decrypt_auto(&self, data: &mut [u8]) {
+        self.crypt_auto(&self.key.decsched, data);
+    }
+
+    fn crypt_auto(&self, sched: &[IceSubkey], data: &mut [u8]) {
+        if self.kernel == Kernel::Bitsliced {
+            self.crypt(sched, data);
+            return;
+        }
+
+        let calibration = Calibration::global();
+        let path = calibration.execution(data.len());
+        self.crypt_on(path, calibration.kernel, sched, data);
+    }
+
+    /*
+     * Split the messages into runs of roughly MANY_BATCH bytes. A message
+     * at least that long ends up in a run of its own.
+     */
+    fn many_batches<'a, 'b>(bufs: &'a mut [&'b mut [u8]]) -> Vec<&'a mut [&'b mut [u8]]> {
+        let mut batches = Vec::new();
+        let mut rest = bufs;
+        while !rest.is_empty() {
+            let mut bytes = 0;
+            let end = rest
+                .iter()
+                .position(|buf| {
+                    bytes += buf.len();
+                    bytes >= MANY_BATCH
+                })
+                .map_or(rest.len(), |i| i.max(1));
+            let (batch, tail) = std::mem::take(&mut rest).split_at_mut(end);
+            batches.push(batch);
+            rest = tail;
+        }
+        batches
+    }
+
+    /*
+     * Gather a batch of messages into `scratch`, run it through `crypt` as
+     * one buffer and scatter the result back.
+     */
+    fn crypt_gathered(
+        batch: &mut [&mut [u8]],
+        scratch: &mut Vec<u8>,
+        crypt: impl FnOnce(&mut [u8]),
+    ) {
+        if let [buf] = batch {
+            crypt(buf);
+            return;
+        }
+
+        scratch.clear();
+        for buf in batch.iter() {
+            assert!(buf.len().is_multiple_of(8), "Data must be a multiple of 8 bytes");
+            scratch.extend_from_slice(buf);
+        }
+
+        crypt(scratch);
+
+        let mut offset = 0;
+        for buf in batch.iter_mut() {
+            buf.copy_from_slice(&scratch[offset..offset + buf.len()]);
+            offset += buf.len();
+        }
+    }
+
+    fn crypt_many(&self, sched: &[IceSubkey], bufs: &mut [&mut [u8]]) {
+        let mut scratch = Vec::with_capacity(MANY_BATCH * 2);
+        for batch in Self::many_batches(bufs) {
+            Self::crypt_gathered(batch, &mut scratch, |data| self.crypt(sched, data));
+        }
+    }
+
+    /*
+     * Blocks are independent, so all of the messages can be gathered into
+     * one buffer and split across the workers without regard to where the
+     * messages start and end.
+     */
+    fn crypt_many_par(&self, sched: &[IceSubkey], bufs: &mut [&mut [u8]]) {
+        let mut scratch = Vec::with_capacity(bufs.iter().map(|buf| buf.len()).sum());
+        Self::crypt_gathered(bufs, &mut scratch, |data| {
+            self.crypt_par(sched, data, &ParOptions::default())
         });
+    }
 
-        chunk[0..4].copy_from_slice(&r.to_be_bytes()[..]);
-        chunk[4..8].copy_from_slice(&l.to_be_bytes()[..]);
+    /// Encrypt data in-place.
+    pub fn encrypt(&self, data: &mut [u8]) {
+        self.crypt(&self.key.keysched, data);
     }
 
     /// Encrypt data in-place using 'par_chunks'.
     pub fn encrypt_par(&self, data: &mut [u8]) {
-        assert!(data.len() % 8 == 0, "Data must be a multiple of 8 bytes");
+        self.crypt_par(&self.key.keysched, data, &ParOptions::default());
+    }
 
-        data.par_chunks_exact_mut(16).for_each(|chunk| {
-            self.encrypt_16(chunk);
-        });
+    /// Encrypt data in-place using 'par_chunks' tuned by `options`.
+    pub fn encrypt_par_with(&self, data: &mut [u8], options: &ParOptions) {
+        self.crypt_par(&self.key.keysched, data, options);
+    }
 
-        data.par_chunks_exact_mut(16)
-            .into_remainder()
-            .chunks_exact_mut(8)
-            .for_each(|chunk| {
-                self.encrypt_8(chunk);
-            })
+    /// Encrypt data in-place like `encrypt_par_with`, reporting progress
+    /// and stopping early when cancelled, see `ProgressOptions`.
+    pub fn encrypt_par_progress(
+        &self,
+        data: &mut [u8],
+        options: &ProgressOptions,
+    ) -> Result<(), Cancelled> {
+        self.crypt_par_progress(&self.key.keysched, data, options)
     }
 
     /// Decrypt data in-place.
     pub fn decrypt(&self, data: &mut [u8]) {
-        assert!(data.len() % 8 == 0, "Data must be a multiple of 8 bytes");
-
-        data.chunks_exact_mut(16).for_each(|chunk| {
-            self.decrypt_16(chunk);
-        });
-
-        data.chunks_exact_mut(16)
-            .into_remainder()
-            .chunks_exact_mut(8)
-            .for_each(|chunk| {
-                self.decrypt_8(chunk);
-            });
+        self.crypt(&self.key.decsched, data);
     }
 
     /// Decrypt data in-place using 'par_chunks'.
     pub fn decrypt_par(&self, data: &mut [u8]) {
-        // See the notes in encrypt_par
-        assert!(data.len() % 8 == 0, "Data must be a multiple of 8 bytes");
+        self.crypt_par(&self.key.decsched, data, &ParOptions::default());
+    }
 
-        data.par_chunks_exact_mut(16).for_each(|chunk| {
-            self.decrypt_16(chunk);
-        });
+    /// Decrypt data in-place using 'par_chunks' tuned by `options`.
+    pub fn decrypt_par_with(&self, data: &mut [u8], options: &ParOptions) {
+        self.crypt_par(&self.key.decsched, data, options);
+    }
 
-        data.par_chunks_exact_mut(16)
-            .into_remainder()
-            .chunks_exact_mut(8)
-            .for_each(|chunk| {
-                self.decrypt_8(chunk);
-            });
+    /// Decrypt data in-place, see `encrypt_par_progress`.
+    pub fn decrypt_par_progress(
+        &self,
+        data: &mut [u8],
+        options: &ProgressOptions,
+    ) -> Result<(), Cancelled> {
+        self.crypt_par_progress(&self.key.decsched, data, options)
+    }
+
+    /// Encrypt many independent messages in-place.
+    ///
+    /// Small messages are gathered into batches so the blocks of different
+    /// messages share the wide kernels instead of each call running its own
+    /// short tail. Every message must be a multiple of 8 bytes.
+    pub fn encrypt_many(&self, bufs: &mut [&mut [u8]]) {
+        self.crypt_many(&self.key.keysched, bufs);
+    }
+
+    /// Encrypt many independent messages in-place, spreading the blocks
+    /// across the default parallel backend.
+    ///
+    /// Unlike `encrypt_many` this doesn't batch: all messages are copied
+    /// into one scratch buffer as large as their total length, which is
+    /// split across the workers regardless of message boundaries.
+    pub fn encrypt_many_par(&self, bufs: &mut [&mut [u8]]) {
+        self.crypt_many_par(&self.key.keysched, bufs);
+    }
+
+    /// Decrypt many independent messages in-place, see `encrypt_many`.
+    pub fn decrypt_many(&self, bufs: &mut [&mut [u8]]) {
+        self.crypt_many(&self.key.decsched, bufs);
+    }
+
+    /// Decrypt many independent messages in-place, spreading the blocks
+    /// across the default parallel backend.
+    pub fn decrypt_many_par(&self, bufs: &mut [&mut [u8]]) {
+        self.crypt_many_par(&self.key.decsched, bufs);
+    }
+
+    /// Encrypt fragments in-place as if they were one buffer.
+    ///
+    /// The fragments can be any length, only the total has to be a multiple
+    /// of 8 bytes. Whole blocks inside a fragment go through the kernels
+    /// where they are, a block split across fragments through a bounce
+    /// buffer.
+    pub fn encrypt_vectored(&self, bufs: &mut [io::IoSliceMut<'_>]) {
+        self.crypt_vectored(&self.key.keysched, bufs);
+    }
+
+    /// Decrypt fragments in-place as if they were one buffer, see
+    /// `encrypt_vectored`.
+    pub fn decrypt_vectored(&self, bufs: &mut [io::IoSliceMut<'_>]) {
+        self.crypt_vectored(&self.key.decsched, bufs);
+    }
+
+    fn crypt_vectored(&self, sched: &[IceSubkey], bufs: &mut [io::IoSliceMut<'_>]) {
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        assert!(total.is_multiple_of(8), "Data must be a multiple of 8 bytes");
+
+        // The straddling block, and the (fragment, offset) each of its
+        // bytes came from, a block can span up to 8 tiny fragments
+        let mut bounce = [0u8; 8];
+        let mut from = [(0, 0); 8];
+        let mut held = 0;
+        for i in 0..bufs.len() {
+            let len = bufs[i].len();
+            let mut pos = 0;
+            if held > 0 {
+                let take = (8 - held).min(len);
+                bounce[held..held + take].copy_from_slice(&bufs[i][..take]);
+                from[held..held + take]
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(j, at)| *at = (i, j));
+                held += take;
+                pos = take;
+                if held == 8 {
+                    self.crypt(sched, &mut bounce);
+                    for (byte, &(buf, at)) in bounce.iter().zip(&from) {
+                        bufs[buf][at] = *byte;
+                    }
+                    held = 0;
+                }
+            }
+
+            let run = (len - pos) / 8 * 8;
+            self.crypt(sched, &mut bufs[i][pos..pos + run]);
+            pos += run;
+
+            let rest = len - pos;
+            if rest > 0 {
+                bounce[..rest].copy_from_slice(&bufs[i][pos..]);
+                from[..rest]
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(j, at)| *at = (i, pos + j));
+                held = rest;
+            }
+        }
     }
 
     /*
@@ -351,7 +1480,7 @@ impl Ice {
      */
     fn key_sched_build(&mut self, kb: &mut [u16; 4], n: i32, keyrot: &[i32]) {
         for (i, kr) in keyrot.iter().enumerate().take(8) {
-            let isk: &mut IceSubkey = &mut self.key.keysched[n as usize + i as usize];
+            let isk: &mut IceSubkey = &mut self.key.keysched[n as usize + i];
             isk.val.fill(0);
 
             for j in 0..15 {
@@ -372,6 +1501,31 @@ impl Ice {
      * Set the key schedule of an ICE key.
      */
     pub fn key_set(&mut self, key: &[u8]) {
+        key_sched_fill(key, self.key.size, &mut self.key.keysched);
+        self.decsched_build();
+    }
+
+    /// Set the key schedule with the bit-serial routine from the reference
+    /// implementation. Much slower than `key_set`, kept to check it against.
+    pub fn key_set_bitwise(&mut self, key: &[u8]) {
+        self.key_set_with(key, Self::key_sched_build);
+        self.decsched_build();
+    }
+
+    /*
+     * Reverse the schedule for decryption. Reversing also swaps the
+     * subkeys within each round pair, so decrypt uses pair[0] then
+     * pair[1] exactly like encrypt.
+     */
+    fn decsched_build(&mut self) {
+        let key = &mut self.key;
+        key.decsched
+            .iter_mut()
+            .zip(key.keysched.iter().rev())
+            .for_each(|(d, e)| *d = *e);
+    }
+
+    fn key_set_with(&mut self, key: &[u8], build: fn(&mut Self, &mut [u16; 4], i32, &[i32])) {
         if self.key.rounds == 8 {
             let mut kb: [u16; 4] = [0; 4];
 
@@ -379,7 +1533,7 @@ impl Ice {
                 kb[3 - i] = (key[i * 2] as u16) << 8 | key[i * 2 + 1] as u16;
             }
 
-            self.key_sched_build(&mut kb, 0, &KEYROT);
+            build(self, &mut kb, 0, &KEYROT);
             return;
         }
 
@@ -388,11 +1542,12 @@ impl Ice {
 
             for j in 0..4 {
                 kb[3 - j] =
-                    (key[i * 8 + j * 2] as u16) << 8 | key[i as usize * 8 + j * 2 + 1] as u16;
+                    (key[i * 8 + j * 2] as u16) << 8 | key[i * 8 + j * 2 + 1] as u16;
             }
 
-            self.key_sched_build(&mut kb, (i * 8).try_into().unwrap(), &KEYROT);
-            self.key_sched_build(
+            build(self, &mut kb, (i * 8).try_into().unwrap(), &KEYROT);
+            build(
+                self,
                 &mut kb,
                 (self.key.rounds - 8 - i * 8).try_into().unwrap(),
                 &KEYROT[8..16],
@@ -408,3 +1563,359 @@ impl Ice {
         (self.key.size * 8).try_into().unwrap()
     }
 }
+
+/// Thin-ICE with the key schedule built in a `const` context.
+///
+/// `static DECRYPTOR: ThinIce = ThinIce::from_const_key([..]);` needs no
+/// startup work and can live in read-only memory. It always runs the
+/// unrolled table kernel, without kernel selection or `_par` variants.
+#[derive(Clone, Debug)]
+pub struct ThinIce {
+    keysched: [IceSubkey; 8],
+    decsched: [IceSubkey; 8],
+}
+
+impl ThinIce {
+    /// Build the Thin-ICE key schedule for `key`, usable in `const` and
+    /// `static` initialisers.
+    pub const fn from_const_key(key: [u8; 8]) -> Self {
+        let mut kb = [0u16; 4];
+        let mut i = 0;
+        while i < 4 {
+            kb[3 - i] = (key[i * 2] as u16) << 8 | key[i * 2 + 1] as u16;
+            i += 1;
+        }
+
+        let mut keysched = [IceSubkey { val: [0; 3] }; 8];
+        key_sched_spread(&mut kb, &KEYROT, &mut keysched);
+
+        let mut decsched = keysched;
+        i = 0;
+        while i < 8 {
+            decsched[i] = keysched[7 - i];
+            i += 1;
+        }
+
+        ThinIce { keysched, decsched }
+    }
+
+    fn crypt(sched: &[IceSubkey; 8], data: &mut [u8]) {
+        assert!(data.len().is_multiple_of(8), "Data must be a multiple of 8 bytes");
+
+        let (bulk, tail) = data.split_at_mut(data.len() / (INTERLEAVE * 8) * INTERLEAVE * 8);
+        crypt_thin::<INTERLEAVE>(&ICE_SBOX, sched, bulk);
+        crypt_thin::<1>(&ICE_SBOX, sched, tail);
+    }
+
+    /// Encrypt data in-place.
+    pub fn encrypt(&self, data: &mut [u8]) {
+        Self::crypt(&self.keysched, data);
+    }
+
+    /// Decrypt data in-place.
+    pub fn decrypt(&self, data: &mut [u8]) {
+        Self::crypt(&self.decsched, data);
+    }
+}
+
+/*
+ * Multi-block gather kernels.
+ *
+ * Each lane holds one block, so the expansion and salt from ice_f_ess run
+ * in vector registers and the four S-box lookups become four gathers from
+ * the shared tables. The Feistel dependency between rounds is unchanged,
+ * the win comes purely from having 8 or 16 blocks in flight.
+ */
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{load_blocks, store_blocks, Ice, IceSubkey};
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    fn ice_f_avx2(ice: &Ice, p: __m256i, sk: &IceSubkey) -> __m256i {
+        let lo = _mm256_set1_epi32(0x3ff);
+        let hi = _mm256_set1_epi32(0xffc00);
+
+        /* Expanded 2x20-bit values */
+        let tr = _mm256_or_si256(
+            _mm256_and_si256(p, lo),
+            _mm256_and_si256(_mm256_slli_epi32::<2>(p), hi),
+        );
+        let rot = _mm256_or_si256(_mm256_slli_epi32::<18>(p), _mm256_srli_epi32::<14>(p));
+        let tl = _mm256_or_si256(
+            _mm256_and_si256(_mm256_srli_epi32::<16>(p), lo),
+            _mm256_and_si256(rot, hi),
+        );
+
+        /* Perform the salt permutation and XOR with the subkey */
+        let salt = _mm256_and_si256(_mm256_set1_epi32(sk.val[2] as i32), _mm256_xor_si256(tl, tr));
+        let al = _mm256_xor_si256(_mm256_xor_si256(salt, tl), _mm256_set1_epi32(sk.val[0] as i32));
+        let ar = _mm256_xor_si256(_mm256_xor_si256(salt, tr), _mm256_set1_epi32(sk.val[1] as i32));
+
+        /* S-box lookup and permutation */
+        let sb0 = _mm256_and_si256(_mm256_srli_epi32::<10>(al), lo);
+        let sb1 = _mm256_and_si256(al, lo);
+        let sb2 = _mm256_and_si256(_mm256_srli_epi32::<10>(ar), lo);
+        let sb3 = _mm256_and_si256(ar, lo);
+        // SAFETY: every index is masked to 10 bits and each table has 1024 entries.
+        unsafe {
+            _mm256_or_si256(
+                _mm256_or_si256(
+                    _mm256_i32gather_epi32::<4>(ice.sbox[0].as_ptr() as *const i32, sb0),
+                    _mm256_i32gather_epi32::<4>(ice.sbox[1].as_ptr() as *const i32, sb1),
+                ),
+                _mm256_or_si256(
+                    _mm256_i32gather_epi32::<4>(ice.sbox[2].as_ptr() as *const i32, sb2),
+                    _mm256_i32gather_epi32::<4>(ice.sbox[3].as_ptr() as *const i32, sb3),
+                ),
+            )
+        }
+    }
+
+    /// Run exactly 8 blocks through `sched`.
+    ///
+    /// # Safety
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn crypt_avx2(ice: &Ice, sched: &[IceSubkey], chunk: &mut [u8]) {
+        assert!(chunk.len() == 64);
+
+        let (la, ra) = load_blocks::<8>(chunk);
+        let mut l = unsafe { _mm256_loadu_si256(la.as_ptr() as *const __m256i) };
+        let mut r = unsafe { _mm256_loadu_si256(ra.as_ptr() as *const __m256i) };
+
+        sched.chunks_exact(2).for_each(|pair| {
+            l = _mm256_xor_si256(l, ice_f_avx2(ice, r, &pair[0]));
+            r = _mm256_xor_si256(r, ice_f_avx2(ice, l, &pair[1]));
+        });
+
+        let (mut la, mut ra) = ([0u32; 8], [0u32; 8]);
+        unsafe {
+            _mm256_storeu_si256(la.as_mut_ptr() as *mut __m256i, l);
+            _mm256_storeu_si256(ra.as_mut_ptr() as *mut __m256i, r);
+        }
+        store_blocks(chunk, &la, &ra);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    fn ice_f_avx512(ice: &Ice, p: __m512i, sk: &IceSubkey) -> __m512i {
+        let lo = _mm512_set1_epi32(0x3ff);
+        let hi = _mm512_set1_epi32(0xffc00);
+
+        /* Expanded 2x20-bit values */
+        let tr = _mm512_or_si512(
+            _mm512_and_si512(p, lo),
+            _mm512_and_si512(_mm512_slli_epi32::<2>(p), hi),
+        );
+        let tl = _mm512_or_si512(
+            _mm512_and_si512(_mm512_srli_epi32::<16>(p), lo),
+            _mm512_and_si512(_mm512_rol_epi32::<18>(p), hi),
+        );
+
+        /* Perform the salt permutation and XOR with the subkey */
+        let salt = _mm512_and_si512(_mm512_set1_epi32(sk.val[2] as i32), _mm512_xor_si512(tl, tr));
+        let al = _mm512_xor_si512(_mm512_xor_si512(salt, tl), _mm512_set1_epi32(sk.val[0] as i32));
+        let ar = _mm512_xor_si512(_mm512_xor_si512(salt, tr), _mm512_set1_epi32(sk.val[1] as i32));
+
+        /* S-box lookup and permutation */
+        let sb0 = _mm512_and_si512(_mm512_srli_epi32::<10>(al), lo);
+        let sb1 = _mm512_and_si512(al, lo);
+        let sb2 = _mm512_and_si512(_mm512_srli_epi32::<10>(ar), lo);
+        let sb3 = _mm512_and_si512(ar, lo);
+        // SAFETY: every index is masked to 10 bits and each table has 1024 entries.
+        unsafe {
+            _mm512_or_si512(
+                _mm512_or_si512(
+                    _mm512_i32gather_epi32::<4>(sb0, ice.sbox[0].as_ptr() as *const i32),
+                    _mm512_i32gather_epi32::<4>(sb1, ice.sbox[1].as_ptr() as *const i32),
+                ),
+                _mm512_or_si512(
+                    _mm512_i32gather_epi32::<4>(sb2, ice.sbox[2].as_ptr() as *const i32),
+                    _mm512_i32gather_epi32::<4>(sb3, ice.sbox[3].as_ptr() as *const i32),
+                ),
+            )
+        }
+    }
+
+    /// Run exactly 16 blocks through `sched`.
+    ///
+    /// # Safety
+    /// The CPU must support AVX-512F.
+    #[target_feature(enable = "avx512f")]
+    pub unsafe fn crypt_avx512(ice: &Ice, sched: &[IceSubkey], chunk: &mut [u8]) {
+        assert!(chunk.len() == 128);
+
+        let (la, ra) = load_blocks::<16>(chunk);
+        let mut l = unsafe { _mm512_loadu_si512(la.as_ptr() as *const __m512i) };
+        let mut r = unsafe { _mm512_loadu_si512(ra.as_ptr() as *const __m512i) };
+
+        sched.chunks_exact(2).for_each(|pair| {
+            l = _mm512_xor_si512(l, ice_f_avx512(ice, r, &pair[0]));
+            r = _mm512_xor_si512(r, ice_f_avx512(ice, l, &pair[1]));
+        });
+
+        let (mut la, mut ra) = ([0u32; 16], [0u32; 16]);
+        unsafe {
+            _mm512_storeu_si512(la.as_mut_ptr() as *mut __m512i, l);
+            _mm512_storeu_si512(ra.as_mut_ptr() as *mut __m512i, r);
+        }
+        store_blocks(chunk, &la, &ra);
+    }
+}
+
+/*
+ * Bitsliced kernel.
+ *
+ * Bit j of the 32-bit half of block i lives in bit i of word j, so every
+ * boolean operation on a word advances 64 blocks at once. The S-boxes are
+ * evaluated from their definition: the column byte is XORed with ICE_SXOR,
+ * raised to the 7th power in GF(2^8) modulo ICE_SMOD for all four rows, and
+ * the row bits select the result with masks. The expansion and P-box are
+ * plain wiring. Subkey bits become all-zero or all-one masks, so nothing
+ * branches on or indexes by key or data.
+ */
+mod bitslice {
+    use super::{IceSubkey, ICE_PBOX, ICE_SMOD, ICE_SXOR};
+
+    pub const LANES: usize = 64;
+
+    type Half = [u64; 32];
+    type Byte = [u64; 8];
+
+    fn mask(v: u32, bit: usize) -> u64 {
+        0u64.wrapping_sub(((v >> bit) & 1) as u64)
+    }
+
+    /* Reduce a 15 term product modulo the degree 8 polynomial m */
+    fn gf_reduce(mut prod: [u64; 15], m: u32) -> Byte {
+        for k in (8..15).rev() {
+            let t = prod[k];
+            for bit in 0..8 {
+                if (m >> bit) & 1 != 0 {
+                    prod[k - 8 + bit] ^= t;
+                }
+            }
+        }
+        prod[..8].try_into().unwrap()
+    }
+
+    fn gf_mult(a: &Byte, b: &Byte, m: u32) -> Byte {
+        let mut prod = [0; 15];
+        for i in 0..8 {
+            for j in 0..8 {
+                prod[i + j] ^= a[i] & b[j];
+            }
+        }
+        gf_reduce(prod, m)
+    }
+
+    /* Squaring is linear in GF(2), the cross terms cancel */
+    fn gf_square(a: &Byte, m: u32) -> Byte {
+        let mut prod = [0; 15];
+        for i in 0..8 {
+            prod[2 * i] = a[i];
+        }
+        gf_reduce(prod, m)
+    }
+
+    /* Same chain of multiplications as gf_exp7 */
+    fn gf_exp7(b: &Byte, m: u32) -> Byte {
+        let x = gf_square(b, m);
+        let x = gf_mult(b, &x, m);
+        let x = gf_square(&x, m);
+        gf_mult(b, &x, m)
+    }
+
+    /*
+     * Evaluate S-box s on 10 input bits and XOR its P-boxed output into f.
+     */
+    fn sbox(s: usize, input: &[u64], f: &mut Half) {
+        let mut out: Byte = [0; 8];
+        for row in 0..4 {
+            let mut col: Byte = [0; 8];
+            for (c, bit) in col.iter_mut().enumerate() {
+                *bit = input[c + 1] ^ mask(ICE_SXOR[s][row] as u32, c);
+            }
+            let v = gf_exp7(&col, ICE_SMOD[s][row] as u32);
+
+            let lo = if row & 1 != 0 { input[0] } else { !input[0] };
+            let hi = if row & 2 != 0 { input[9] } else { !input[9] };
+            let sel = lo & hi;
+            for c in 0..8 {
+                out[c] ^= sel & v[c];
+            }
+        }
+
+        let shift = 24 - 8 * s;
+        for (c, bit) in out.iter().enumerate() {
+            f[ICE_PBOX[shift + c].trailing_zeros() as usize] ^= bit;
+        }
+    }
+
+    fn ice_f(p: &Half, sk: &IceSubkey) -> Half {
+        let mut al = [0; 20];
+        let mut ar = [0; 20];
+        for k in 0..20 {
+            /* Expanded 2x20-bit values */
+            let tr = if k < 10 { p[k] } else { p[k - 2] };
+            let tl = if k < 10 { p[k + 16] } else { p[(k + 14) % 32] };
+
+            /* Perform the salt permutation and XOR with the subkey */
+            let salt = mask(sk.val[2], k) & (tl ^ tr);
+            al[k] = salt ^ tl ^ mask(sk.val[0], k);
+            ar[k] = salt ^ tr ^ mask(sk.val[1], k);
+        }
+
+        let mut f = [0; 32];
+        sbox(0, &al[10..20], &mut f);
+        sbox(1, &al[0..10], &mut f);
+        sbox(2, &ar[10..20], &mut f);
+        sbox(3, &ar[0..10], &mut f);
+        f
+    }
+
+    fn load(chunk: &[u8]) -> (Half, Half) {
+        let mut l = [0; 32];
+        let mut r = [0; 32];
+        chunk.chunks_exact(8).enumerate().for_each(|(i, block)| {
+            let lw = u32::from_be_bytes(block[0..4].try_into().unwrap());
+            let rw = u32::from_be_bytes(block[4..8].try_into().unwrap());
+            for j in 0..32 {
+                l[j] |= (((lw >> j) & 1) as u64) << i;
+                r[j] |= (((rw >> j) & 1) as u64) << i;
+            }
+        });
+        (l, r)
+    }
+
+    fn store(chunk: &mut [u8], l: &Half, r: &Half) {
+        chunk.chunks_exact_mut(8).enumerate().for_each(|(i, block)| {
+            let mut lw = 0u32;
+            let mut rw = 0u32;
+            for j in 0..32 {
+                lw |= (((l[j] >> i) & 1) as u32) << j;
+                rw |= (((r[j] >> i) & 1) as u32) << j;
+            }
+            block[0..4].copy_from_slice(&rw.to_be_bytes()[..]);
+            block[4..8].copy_from_slice(&lw.to_be_bytes()[..]);
+        });
+    }
+
+    fn xor_into(h: &mut Half, f: &Half) {
+        h.iter_mut().zip(f.iter()).for_each(|(h, f)| *h ^= f);
+    }
+
+    /// Run up to 64 blocks through `sched`.
+    pub fn crypt(sched: &[IceSubkey], chunk: &mut [u8]) {
+        assert!(chunk.len() <= LANES * 8 && chunk.len().is_multiple_of(8));
+
+        let (mut l, mut r) = load(chunk);
+
+        sched.chunks_exact(2).for_each(|pair| {
+            xor_into(&mut l, &ice_f(&r, &pair[0]));
+            xor_into(&mut r, &ice_f(&l, &pair[1]));
+        });
+
+        store(chunk, &l, &r);
+    }
+}
@@ -0,0 +1,297 @@
+// Encrypting files in place through a memory map.
+//
+// The file is mapped and the icefast kernels run straight over the
+// mapping with `encrypt_par_with`, one segment at a time. Before a segment
+// is touched its original bytes go into a sidecar journal, so a crash can
+// always be finished off: put the segment back from the journal and carry
+// on from there. Encrypting a segment again from its original bytes gives
+// the same result, so it doesn't matter how far the crash got into it.
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use memmap2::MmapMut;
+
+use crate::error::IceError;
+use crate::icefast::{Ice, ParOptions};
+
+/*
+ * Journal layout (40 byte header, then the segment):
+ *   [0..8]   magic
+ *   [8]      1 encrypting, 2 decrypting
+ *   [9..16]  reserved, zero
+ *   [16..24] key check, the key's encryption of a zero block
+ *   [24..32] file length, big endian
+ *   [32..40] segment offset, big endian, everything before it is done
+ *   [40..]   the segment's original bytes
+ */
+const MAGIC: &[u8; 8] = b"ICEJRNL1";
+const HEADER_LEN: usize = 40;
+
+/// Which way an in-place operation goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+/// Tuning for `encrypt_file_in_place_with` and friends.
+#[derive(Clone, Debug)]
+pub struct InPlaceOptions {
+    segment_len: usize,
+    par: ParOptions,
+}
+
+impl Default for InPlaceOptions {
+    /// 64 MiB segments on the default `ParOptions`.
+    fn default() -> Self {
+        InPlaceOptions {
+            segment_len: 64 << 20,
+            par: ParOptions::default(),
+        }
+    }
+}
+
+impl InPlaceOptions {
+    /// Same as `InPlaceOptions::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes per segment, rounded up to a whole number of blocks. Each one
+    /// is copied to the journal before it is encrypted.
+    pub fn with_segment_len(mut self, bytes: usize) -> Self {
+        self.segment_len = bytes.max(1).checked_next_multiple_of(8).unwrap_or(usize::MAX & !7);
+        self
+    }
+
+    /// How each segment is spread over threads.
+    pub fn with_par(mut self, par: ParOptions) -> Self {
+        self.par = par;
+        self
+    }
+}
+
+/// The journal kept next to `path` while it is encrypted in place.
+pub fn journal_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut journal = path.as_ref().as_os_str().to_owned();
+    journal.push(".icejournal");
+    PathBuf::from(journal)
+}
+
+/// Encrypt the file at `path` in place, returning its length.
+///
+/// The file has to be a multiple of 8 bytes long. Fails with
+/// `IceError::Journal` if an earlier run was interrupted, see
+/// `recover_in_place`.
+///
+/// While this runs, the journal next to the file holds a plaintext copy of
+/// the current segment, up to 64 MiB by default. It is only unlinked at the
+/// end, never overwritten, so the plaintext may stay on the disk. The
+/// journal also holds the key's encryption of a zero block for
+/// `recover_in_place` to check the key with. That is a known plaintext and
+/// ciphertext pair, which is all a brute force key search needs, and
+/// `icefast::encrypt_block_many_keys` makes such a search fast. Only use
+/// this when whoever can read the journal could read the file anyway, and
+/// with keys too large to search.
+pub fn encrypt_file_in_place(ice: &Ice, path: impl AsRef<Path>) -> Result<u64, IceError> {
+    encrypt_file_in_place_with(ice, path, &InPlaceOptions::default())
+}
+
+/// Decrypt the file at `path` in place, see `encrypt_file_in_place`.
+pub fn decrypt_file_in_place(ice: &Ice, path: impl AsRef<Path>) -> Result<u64, IceError> {
+    decrypt_file_in_place_with(ice, path, &InPlaceOptions::default())
+}
+
+/// `encrypt_file_in_place` with explicit options.
+pub fn encrypt_file_in_place_with(
+    ice: &Ice,
+    path: impl AsRef<Path>,
+    options: &InPlaceOptions,
+) -> Result<u64, IceError> {
+    crypt_in_place(ice, path.as_ref(), Direction::Encrypt, options)
+}
+
+/// `decrypt_file_in_place` with explicit options.
+pub fn decrypt_file_in_place_with(
+    ice: &Ice,
+    path: impl AsRef<Path>,
+    options: &InPlaceOptions,
+) -> Result<u64, IceError> {
+    crypt_in_place(ice, path.as_ref(), Direction::Decrypt, options)
+}
+
+/// Finish an in-place operation on `path` that was interrupted, returning
+/// which way it went, or `None` if there was nothing to recover.
+///
+/// Needs the same key, a different one fails with `IceError::BadMac`
+/// before anything is touched.
+pub fn recover_in_place(
+    ice: &Ice,
+    path: impl AsRef<Path>,
+    options: &InPlaceOptions,
+) -> Result<Option<Direction>, IceError> {
+    let path = path.as_ref();
+    let journal = journal_path(path);
+    let mut data = Vec::new();
+    match File::open(&journal) {
+        Ok(mut file) => file.read_to_end(&mut data)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let (header, original) = data
+        .split_at_checked(HEADER_LEN)
+        .ok_or(IceError::BadHeader)?;
+    let field = |at: usize| u64::from_be_bytes(header[at..at + 8].try_into().unwrap());
+    if &header[..8] != MAGIC {
+        return Err(IceError::BadHeader);
+    }
+    let direction = match header[8] {
+        1 => Direction::Encrypt,
+        2 => Direction::Decrypt,
+        _ => return Err(IceError::BadHeader),
+    };
+    if header[16..24] != key_check(ice) {
+        return Err(IceError::BadMac);
+    }
+
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let (len, offset) = (field(24), field(32));
+    let fits = offset.is_multiple_of(8)
+        && offset
+            .checked_add(original.len() as u64)
+            .is_some_and(|end| end <= len);
+    if file.metadata()?.len() != len || !fits {
+        return Err(IceError::BadHeader);
+    }
+
+    // Put the segment back the way it was and carry on from there.
+    // Safety: see `crypt_in_place`.
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    let start = offset as usize;
+    map[start..start + original.len()].copy_from_slice(original);
+    map.flush_range(start, original.len())?;
+    run(ice, &file, &mut map, &journal, direction, start, options)?;
+    Ok(Some(direction))
+}
+
+fn crypt_in_place(
+    ice: &Ice,
+    path: &Path,
+    direction: Direction,
+    options: &InPlaceOptions,
+) -> Result<u64, IceError> {
+    let journal = journal_path(path);
+    if journal.exists() {
+        return Err(IceError::Journal(journal));
+    }
+
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    if !len.is_multiple_of(8) {
+        return Err(IceError::BlockSize(len as usize));
+    }
+    if len == 0 {
+        // Empty files can't be mapped, and there's nothing to do anyway
+        return Ok(0);
+    }
+
+    // Safety: the mapping is only valid as long as nobody else truncates
+    // or rewrites the file behind it, as with any in-place edit.
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    run(ice, &file, &mut map, &journal, direction, 0, options)?;
+    Ok(len)
+}
+
+/*
+ * Encrypt or decrypt `map` from `start` on, journalling each segment
+ * first, then sync and drop the journal.
+ */
+fn run(
+    ice: &Ice,
+    file: &File,
+    map: &mut MmapMut,
+    journal: &Path,
+    direction: Direction,
+    start: usize,
+    options: &InPlaceOptions,
+) -> Result<(), IceError> {
+    let mut header = [0; HEADER_LEN];
+    header[..8].copy_from_slice(MAGIC);
+    header[8] = match direction {
+        Direction::Encrypt => 1,
+        Direction::Decrypt => 2,
+    };
+    header[16..24].copy_from_slice(&key_check(ice));
+    header[24..32].copy_from_slice(&(map.len() as u64).to_be_bytes());
+
+    let mut offset = start;
+    while offset < map.len() {
+        let end = offset.saturating_add(options.segment_len).min(map.len());
+        header[32..40].copy_from_slice(&(offset as u64).to_be_bytes());
+        write_journal(journal, &header, &map[offset..end])?;
+
+        let segment = &mut map[offset..end];
+        match direction {
+            Direction::Encrypt => ice.encrypt_par_with(segment, &options.par),
+            Direction::Decrypt => ice.decrypt_par_with(segment, &options.par),
+        }
+        map.flush_range(offset, end - offset)?;
+        offset = end;
+    }
+
+    map.flush()?;
+    file.sync_all()?;
+    fs::remove_file(journal)?;
+    Ok(())
+}
+
+/*
+ * Replace the journal as a whole, through a temporary file and a rename,
+ * so whatever a crash leaves behind is a complete journal. The rename is
+ * only durable once the directory is synced, and it has to be before the
+ * segment is touched: recovering a half done segment from the journal of
+ * the one before would process its finished part twice.
+ */
+fn write_journal(journal: &Path, header: &[u8], original: &[u8]) -> Result<(), IceError> {
+    let mut tmp = journal.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(header)?;
+    file.write_all(original)?;
+    file.sync_all()?;
+    fs::rename(&tmp, journal)?;
+    sync_dir(journal)?;
+    Ok(())
+}
+
+/*
+ * Sync the directory holding `path`. Only possible on Unix, elsewhere
+ * directories can't be opened as files and the rename has to do.
+ */
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<(), IceError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<(), IceError> {
+    Ok(())
+}
+
+/*
+ * Tells keys apart. E_k(0) is a known plaintext and ciphertext pair, so it
+ * does give a key search something to check against, see
+ * encrypt_file_in_place.
+ */
+fn key_check(ice: &Ice) -> [u8; 8] {
+    let mut block = [0; 8];
+    ice.encrypt(&mut block);
+    block
+}
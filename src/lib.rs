@@ -1,2 +1,15 @@
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+pub mod compress;
+pub mod datagram;
+pub mod error;
+pub mod frame;
 pub mod ice;
 pub mod icefast;
+#[cfg(feature = "mmap")]
+pub mod inplace;
+pub mod mode;
+pub mod patch;
+pub mod pipeline;
+pub mod stream;
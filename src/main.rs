@@ -33,6 +33,10 @@ fn main() {
                     });
                     assert_eq!(plaintext, text.as_bytes());
         },
+        "optimized" if args.get(3).is_some_and(|arg| arg.starts_with("--compress")) => {
+            let test_ice = ice::icefast::Ice::new(0, &ice_key);
+            compressed_roundtrip(&test_ice, text.as_bytes(), &args[3]);
+        }
         "optimized" => {
             let test_ice = ice::icefast::Ice::new(0, &ice_key);
             let mut data = text.as_bytes().to_owned();
@@ -47,8 +51,54 @@ fn main() {
             test_ice.decrypt_par(&mut data);
             assert_eq!(data, text.as_bytes());
         }
+        "optimized_auto" => {
+            // `ice optimized_auto <len> [calibration file]`, measured and saved if missing
+            if let Some(path) = args.get(3) {
+                use ice::icefast::Calibration;
+                match Calibration::load(path) {
+                    Ok(calibration) => calibration.install().unwrap(),
+                    Err(_) => Calibration::global().save(path).unwrap(),
+                }
+            }
+            let test_ice = ice::icefast::Ice::new(0, &ice_key);
+            println!("{:?}", ice::icefast::Calibration::global());
+            println!("{:?} for {} bytes", test_ice.auto_execution(text.len()), text.len());
+            let mut data = text.as_bytes().to_owned();
+            test_ice.encrypt_auto(&mut data);
+            test_ice.decrypt_auto(&mut data);
+            assert_eq!(data, text.as_bytes());
+        }
         _ => {
             panic!("Unknown test: {}", test);
         }
     }
 }
+
+// Compression is opt-in: `ice optimized <len> --compress[=deflate|zstd]`
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+fn compressed_roundtrip(test_ice: &ice::icefast::Ice, text: &[u8], flag: &str) {
+    use ice::compress::{compress_and_encrypt, decrypt_and_decompress, Compression};
+
+    let compression = match flag {
+        #[cfg(feature = "deflate")]
+        "--compress" | "--compress=deflate" => Compression::Deflate,
+        #[cfg(all(feature = "zstd", not(feature = "deflate")))]
+        "--compress" => Compression::Zstd,
+        #[cfg(feature = "zstd")]
+        "--compress=zstd" => Compression::Zstd,
+        _ => panic!("Unknown or unsupported flag: {}", flag),
+    };
+
+    let data = compress_and_encrypt(test_ice, text, compression).unwrap();
+    println!("compressed {} bytes into {} ({:?})", text.len(), data.len(), compression);
+    let plain = decrypt_and_decompress(test_ice, &data).unwrap();
+    assert_eq!(plain, text);
+}
+
+#[cfg(not(any(feature = "deflate", feature = "zstd")))]
+fn compressed_roundtrip(_test_ice: &ice::icefast::Ice, _text: &[u8], flag: &str) {
+    panic!(
+        "{} requires building with the `deflate` or `zstd` feature",
+        flag
+    );
+}
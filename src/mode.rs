@@ -0,0 +1,362 @@
+// Block cipher modes on top of icefast::Ice for the streaming adapters.
+//
+// icefast::Ice::encrypt is plain ECB over whole blocks. The adapters in
+// `stream` need chaining, counters and a way to end on a partial block,
+// which all live here so every adapter treats them the same way.
+use crate::error::IceError;
+use crate::icefast::Ice;
+
+/// How consecutive blocks are tied together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Every block on its own, equal plaintext blocks give equal ciphertext.
+    Ecb,
+    /// Each plaintext block is XORed with the previous ciphertext block, the
+    /// first one with `iv`.
+    Cbc { iv: [u8; 8] },
+    /// The data is XORed with the encryption of `nonce + block index` as a
+    /// big endian counter, so any length works and any block can be reached
+    /// directly.
+    Ctr { nonce: [u8; 8] },
+}
+
+/// How ECB and CBC deal with a final partial block. CTR never pads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// The data has to be a multiple of 8 bytes.
+    None,
+    /// PKCS#7, 1 to 8 bytes each holding the pad length, always added.
+    Pkcs7,
+    /// Ciphertext stealing with the last two blocks swapped (CS3), so the
+    /// output is as long as the input. Needs at least one full block.
+    Stealing,
+}
+
+/// Encrypt a whole message in one go, the same as writing it through a
+/// `stream::IceWriter` and finishing.
+pub fn encrypt(ice: &Ice, mode: Mode, padding: Padding, data: &[u8]) -> Result<Vec<u8>, IceError> {
+    let mut chain = Chain::new(mode);
+    let mut pending = data.to_vec();
+    let len = data.len() - chain.hold_back(padding, data.len());
+    chain.encrypt(ice, &mut pending[..len]);
+    let tail = pending.split_off(len);
+    chain.encrypt_final(ice, padding, &tail, data.len() as u64, &mut pending)?;
+    Ok(pending)
+}
+
+/// Decrypt a whole message in one go, see `encrypt`.
+pub fn decrypt(ice: &Ice, mode: Mode, padding: Padding, data: &[u8]) -> Result<Vec<u8>, IceError> {
+    let mut chain = Chain::new(mode);
+    let mut pending = data.to_vec();
+    let len = data.len() - chain.hold_back_decrypt(padding, data.len());
+    chain.decrypt(ice, &mut pending[..len]);
+    let tail = pending.split_off(len);
+    chain.decrypt_final(ice, padding, &tail, data.len() as u64, &mut pending)?;
+    Ok(pending)
+}
+
+/// An 8 byte tag over `data` for encrypt-then-MAC, using a key that is
+/// only ever used for tags.
+///
+/// CBC-MAC with a zero IV over the length as a big endian block, then the
+/// data zero padded to whole blocks. The length up front keeps messages of
+/// different lengths from extending each other.
+pub fn mac(ice: &Ice, data: &[u8]) -> [u8; 8] {
+    let mut tag = (data.len() as u64).to_be_bytes();
+    ice.encrypt(&mut tag);
+    for chunk in data.chunks(8) {
+        xor(&mut tag, chunk);
+        ice.encrypt(&mut tag);
+    }
+    tag
+}
+
+/// Compare two tags, or truncated tags, without bailing out at the first
+/// difference.
+pub fn mac_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/*
+ * Running state of a mode: the previous ciphertext block for CBC and the
+ * index of the next block for CTR.
+ */
+#[derive(Clone, Debug)]
+pub(crate) struct Chain {
+    mode: Mode,
+    prev: [u8; 8],
+    block: u64,
+}
+
+fn xor(data: &mut [u8], with: &[u8]) {
+    data.iter_mut().zip(with).for_each(|(d, w)| *d ^= w);
+}
+
+impl Chain {
+    pub(crate) fn new(mode: Mode) -> Self {
+        let prev = match mode {
+            Mode::Cbc { iv } => iv,
+            _ => [0; 8],
+        };
+        Chain {
+            mode,
+            prev,
+            block: 0,
+        }
+    }
+
+    /*
+     * Pick a chain up at block `block` without going through the ones
+     * before it. `prev` is the ciphertext block before it for CBC, the IV
+     * for block 0, and is ignored by the other modes.
+     */
+    pub(crate) fn resume(mode: Mode, block: u64, prev: [u8; 8]) -> Self {
+        Chain { mode, prev, block }
+    }
+
+    /*
+     * Bytes to keep back from encrypt until the end is known: the partial
+     * block, plus the last full block when it may have to be stolen from.
+     */
+    pub(crate) fn hold_back(&self, padding: Padding, len: usize) -> usize {
+        match (self.mode, padding) {
+            (Mode::Ctr { .. }, _) => len % 8,
+            (_, Padding::Stealing) => (len % 8 + 8).min(len),
+            _ => len % 8,
+        }
+    }
+
+    /*
+     * Same as `hold_back` for ciphertext. PKCS#7 also keeps the last full
+     * block, its padding can't be checked until the input has ended.
+     */
+    pub(crate) fn hold_back_decrypt(&self, padding: Padding, len: usize) -> usize {
+        match (self.mode, padding) {
+            (Mode::Ctr { .. }, _) => len % 8,
+            (_, Padding::Stealing | Padding::Pkcs7) => (len % 8 + 8).min(len),
+            _ => len % 8,
+        }
+    }
+
+    /*
+     * XOR `data` with the keystream from the current block on. Only the
+     * last call may end on a partial block.
+     */
+    fn apply_keystream(&mut self, ice: &Ice, nonce: [u8; 8], data: &mut [u8]) {
+        let nonce = u64::from_be_bytes(nonce);
+        let mut stream = vec![0; data.len().next_multiple_of(8)];
+        for (i, block) in stream.chunks_exact_mut(8).enumerate() {
+            let counter = nonce.wrapping_add(self.block).wrapping_add(i as u64);
+            block.copy_from_slice(&counter.to_be_bytes());
+        }
+        // All counter blocks are independent, so they get the wide kernels
+        ice.encrypt(&mut stream);
+        xor(data, &stream);
+        self.block += (data.len() / 8) as u64;
+    }
+
+    /// Encrypt whole blocks in place, carrying the chaining state on.
+    pub(crate) fn encrypt(&mut self, ice: &Ice, data: &mut [u8]) {
+        assert!(
+            data.len().is_multiple_of(8),
+            "Data must be a multiple of 8 bytes"
+        );
+        match self.mode {
+            Mode::Ecb => ice.encrypt(data),
+            Mode::Cbc { .. } => {
+                for block in data.chunks_exact_mut(8) {
+                    xor(block, &self.prev);
+                    ice.encrypt(block);
+                    self.prev.copy_from_slice(block);
+                }
+            }
+            Mode::Ctr { nonce } => self.apply_keystream(ice, nonce, data),
+        }
+    }
+
+    /// Decrypt whole blocks in place, carrying the chaining state on.
+    pub(crate) fn decrypt(&mut self, ice: &Ice, data: &mut [u8]) {
+        assert!(
+            data.len().is_multiple_of(8),
+            "Data must be a multiple of 8 bytes"
+        );
+        match self.mode {
+            Mode::Ecb => ice.decrypt(data),
+            Mode::Cbc { .. } if !data.is_empty() => {
+                // Unlike encryption every block is independent here, so the
+                // whole run goes through the wide kernels and is XORed after
+                let cipher = data.to_vec();
+                ice.decrypt(data);
+                xor(&mut data[..8], &self.prev);
+                xor(&mut data[8..], &cipher);
+                self.prev.copy_from_slice(&cipher[cipher.len() - 8..]);
+            }
+            Mode::Cbc { .. } => {}
+            Mode::Ctr { nonce } => self.apply_keystream(ice, nonce, data),
+        }
+    }
+
+    /*
+     * Decrypt everything in `pending` that `hold_back_decrypt` lets go of
+     * and move it to the end of `out`.
+     */
+    pub(crate) fn decrypt_ready(
+        &mut self,
+        ice: &Ice,
+        padding: Padding,
+        pending: &mut Vec<u8>,
+        out: &mut Vec<u8>,
+    ) {
+        let len = pending.len() - self.hold_back_decrypt(padding, pending.len());
+        self.decrypt(ice, &mut pending[..len]);
+        out.extend(pending.drain(..len));
+    }
+
+    /*
+     * Encrypt what `hold_back` kept back once the input has ended and
+     * append it to `out`. `total` is the whole input length, for errors.
+     */
+    pub(crate) fn encrypt_final(
+        &mut self,
+        ice: &Ice,
+        padding: Padding,
+        tail: &[u8],
+        total: u64,
+        out: &mut Vec<u8>,
+    ) -> Result<(), IceError> {
+        let start = out.len();
+        out.extend_from_slice(tail);
+        let data = &mut out[start..];
+
+        if let Mode::Ctr { nonce } = self.mode {
+            self.apply_keystream(ice, nonce, data);
+            return Ok(());
+        }
+
+        match padding {
+            _ if data.is_empty() && padding != Padding::Pkcs7 => Ok(()),
+            Padding::None | Padding::Stealing if data.len() < 8 => {
+                Err(IceError::BlockSize(total as usize))
+            }
+            Padding::None => {
+                self.encrypt(ice, data);
+                Ok(())
+            }
+            Padding::Pkcs7 => {
+                let pad = 8 - tail.len();
+                out.resize(start + 8, pad as u8);
+                self.encrypt(ice, &mut out[start..]);
+                Ok(())
+            }
+            Padding::Stealing => {
+                self.encrypt_stolen(ice, data);
+                Ok(())
+            }
+        }
+    }
+
+    /*
+     * Decrypt what `hold_back_decrypt` kept back once the input has ended,
+     * strip the padding and append the plaintext to `out`.
+     */
+    pub(crate) fn decrypt_final(
+        &mut self,
+        ice: &Ice,
+        padding: Padding,
+        tail: &[u8],
+        total: u64,
+        out: &mut Vec<u8>,
+    ) -> Result<(), IceError> {
+        let start = out.len();
+        out.extend_from_slice(tail);
+        let data = &mut out[start..];
+
+        if let Mode::Ctr { nonce } = self.mode {
+            self.apply_keystream(ice, nonce, data);
+            return Ok(());
+        }
+
+        match padding {
+            _ if data.is_empty() && padding != Padding::Pkcs7 => Ok(()),
+            Padding::None | Padding::Stealing if data.len() < 8 => {
+                Err(IceError::BlockSize(total as usize))
+            }
+            Padding::None => {
+                self.decrypt(ice, data);
+                Ok(())
+            }
+            // Even empty plaintext has a whole block of padding
+            Padding::Pkcs7 if data.is_empty() => Err(IceError::BadPadding),
+            Padding::Pkcs7 if data.len() != 8 => {
+                out.truncate(start);
+                Err(IceError::BlockSize(total as usize))
+            }
+            Padding::Pkcs7 => {
+                self.decrypt(ice, data);
+                let pad = data[7];
+                if !(1..=8).contains(&pad) || data[8 - pad as usize..].iter().any(|&b| b != pad) {
+                    out.truncate(start);
+                    return Err(IceError::BadPadding);
+                }
+                out.truncate(out.len() - pad as usize);
+                Ok(())
+            }
+            Padding::Stealing => {
+                self.decrypt_stolen(ice, data);
+                Ok(())
+            }
+        }
+    }
+
+    /*
+     * Ciphertext stealing over one full block and 0 to 7 more bytes. The
+     * full block is encrypted as usual, the partial one is filled up from
+     * its tail and encrypted, and the pair goes out swapped with the first
+     * one cut short.
+     */
+    fn encrypt_stolen(&mut self, ice: &Ice, data: &mut [u8]) {
+        let m = data.len() - 8;
+        let (full, part) = data.split_at_mut(8);
+        self.encrypt(ice, full);
+        if m == 0 {
+            return;
+        }
+
+        let mut last = [0; 8];
+        last[..m].copy_from_slice(part);
+        match self.mode {
+            Mode::Cbc { .. } => xor(&mut last, full),
+            _ => last[m..].copy_from_slice(&full[m..]),
+        }
+        ice.encrypt(&mut last);
+
+        part.copy_from_slice(&full[..m]);
+        full.copy_from_slice(&last);
+    }
+
+    /*
+     * Undo `encrypt_stolen`. Decrypting the first block gives back the
+     * filled up partial block, whose tail completes the cut short
+     * ciphertext of the block before it.
+     */
+    fn decrypt_stolen(&mut self, ice: &Ice, data: &mut [u8]) {
+        let m = data.len() - 8;
+        if m == 0 {
+            self.decrypt(ice, data);
+            return;
+        }
+
+        let (last, part) = data.split_at_mut(8);
+        ice.decrypt(last);
+        let mut full = [0; 8];
+        full[..m].copy_from_slice(part);
+        full[m..].copy_from_slice(&last[m..]);
+        if let Mode::Cbc { .. } = self.mode {
+            xor(&mut last[..m], part);
+        }
+
+        part.copy_from_slice(&last[..m]);
+        last.copy_from_slice(&full);
+        self.decrypt(ice, last);
+    }
+}
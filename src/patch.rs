@@ -0,0 +1,168 @@
+// Patching plaintext in place inside encrypted files.
+//
+// ECB and CTR blocks only depend on their position, so a patch touches
+// just the blocks it overlaps: those are read, decrypted, patched,
+// encrypted again and written back over themselves. CBC carries every
+// ciphertext block into the next one, a patch there is refused with the
+// range that would have to be re-encrypted.
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::IceError;
+use crate::icefast::Ice;
+use crate::mode::{Chain, Mode, Padding};
+
+/// Ciphertext bytes that change when `len` plaintext bytes at `offset`
+/// are patched in a file of `cipher_len` bytes.
+///
+/// That's the overlapped blocks for ECB and CTR, widened to the last two
+/// blocks when it reaches the padding or stolen blocks at the end, and
+/// everything from the first overlapped block on for CBC. Whatever lies
+/// past `cipher_len` is left out.
+pub fn affected_range(
+    mode: Mode,
+    padding: Padding,
+    cipher_len: u64,
+    offset: u64,
+    len: usize,
+) -> Range<u64> {
+    let start = (offset - offset % 8).min(cipher_len);
+    let end = offset
+        .saturating_add(len as u64)
+        .min(cipher_len)
+        .checked_next_multiple_of(8)
+        .map_or(cipher_len, |end| end.min(cipher_len));
+    let tail_start =
+        cipher_len - Chain::new(mode).hold_back_decrypt(padding, cipher_len as usize) as u64;
+    match mode {
+        _ if len == 0 => offset..offset,
+        _ if start == cipher_len => start..start,
+        Mode::Cbc { .. } => start..cipher_len,
+        _ if end > tail_start => start.min(tail_start)..cipher_len,
+        _ => start..end,
+    }
+}
+
+/// Overwrite the plaintext at `offset` with `bytes` in the encrypted file
+/// at `path`, see `patch`.
+pub fn patch_file(
+    ice: &Ice,
+    path: impl AsRef<Path>,
+    mode: Mode,
+    padding: Padding,
+    offset: u64,
+    bytes: &[u8],
+) -> Result<Range<u64>, IceError> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    patch(ice, &mut file, mode, padding, offset, bytes)
+}
+
+/// Overwrite the plaintext at `offset` with `bytes` in the encrypted
+/// `file`, returning the ciphertext range that was rewritten.
+///
+/// The patch has to lie within the plaintext, the length of the file
+/// never changes. An empty patch does nothing, it doesn't even touch
+/// `file`. Only ECB and CTR can be patched, CBC fails with
+/// `IceError::Chained` and the range to re-encrypt before anything is
+/// read or written.
+///
+/// A patched CTR block is encrypted again under the same counter, so
+/// anyone holding both versions of the file can XOR them and gets the old
+/// plaintext XOR the new. Only patch CTR files when that doesn't matter,
+/// or re-encrypt under a fresh nonce instead.
+pub fn patch<F: Read + Write + Seek>(
+    ice: &Ice,
+    file: &mut F,
+    mode: Mode,
+    padding: Padding,
+    offset: u64,
+    bytes: &[u8],
+) -> Result<Range<u64>, IceError> {
+    if bytes.is_empty() {
+        return Ok(offset..offset);
+    }
+
+    let cipher_len = file.seek(SeekFrom::End(0))?;
+    let past_end = || {
+        IceError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "patch runs past the end of the plaintext",
+        ))
+    };
+    // The plaintext is never longer than the ciphertext, the exact check
+    // has to wait until the end is decrypted
+    let patch_end = offset
+        .checked_add(bytes.len() as u64)
+        .filter(|&end| end <= cipher_len)
+        .ok_or_else(past_end)?;
+
+    // Not empty, the patch lies within the file, so CBC always has
+    // something to re-encrypt and never gets a chain resumed below
+    let range = affected_range(mode, padding, cipher_len, offset, bytes.len());
+    if let Mode::Cbc { .. } = mode {
+        return Err(IceError::Chained(range));
+    }
+
+    let held = Chain::new(mode).hold_back_decrypt(padding, cipher_len as usize);
+    let tail_start = cipher_len - held as u64;
+
+    // The end gets decrypted anyway to know where the plaintext stops
+    let mut cipher = vec![0; held];
+    file.seek(SeekFrom::Start(tail_start))?;
+    file.read_exact(&mut cipher)?;
+    let mut tail = Vec::with_capacity(held);
+    Chain::resume(mode, tail_start / 8, [0; 8])
+        .decrypt_final(ice, padding, &cipher, cipher_len, &mut tail)?;
+    let plain_len = tail_start + tail.len() as u64;
+    if patch_end > plain_len {
+        return Err(past_end());
+    }
+
+    // Whole blocks before the end
+    let body = range.start..range.end.min(tail_start);
+    if !body.is_empty() {
+        let mut data = vec![0; (body.end - body.start) as usize];
+        file.seek(SeekFrom::Start(body.start))?;
+        file.read_exact(&mut data)?;
+        Chain::resume(mode, body.start / 8, [0; 8]).decrypt(ice, &mut data);
+        overwrite(&mut data, body.start, offset, bytes);
+        Chain::resume(mode, body.start / 8, [0; 8]).encrypt(ice, &mut data);
+        file.seek(SeekFrom::Start(body.start))?;
+        file.write_all(&data)?;
+    }
+
+    // The end goes back through the padding as a whole
+    if range.end > tail_start {
+        overwrite(&mut tail, tail_start, offset, bytes);
+        cipher.clear();
+        Chain::resume(mode, tail_start / 8, [0; 8]).encrypt_final(
+            ice,
+            padding,
+            &tail,
+            plain_len,
+            &mut cipher,
+        )?;
+        debug_assert_eq!(cipher.len(), held);
+        file.seek(SeekFrom::Start(tail_start))?;
+        file.write_all(&cipher)?;
+    }
+
+    file.flush()?;
+    Ok(range)
+}
+
+/*
+ * Copy the part of `bytes`, meant for plaintext offset `offset`, that
+ * falls into `data`, which starts at plaintext offset `start`.
+ */
+fn overwrite(data: &mut [u8], start: u64, offset: u64, bytes: &[u8]) {
+    let end = start + data.len() as u64;
+    let from = offset.max(start);
+    let to = (offset + bytes.len() as u64).min(end);
+    if from < to {
+        data[(from - start) as usize..(to - start) as usize]
+            .copy_from_slice(&bytes[(from - offset) as usize..(to - offset) as usize]);
+    }
+}
@@ -0,0 +1,306 @@
+// Pipelined encryption of files and streams that don't fit in memory.
+//
+// A reader thread fills fixed size segments, a pool of workers runs the
+// icefast kernels on them and the calling thread writes them back out in
+// order. The segments are a fixed set of buffers handed around in a loop,
+// so memory stays at `in_flight` segments whatever the input size. Only
+// modes whose blocks don't depend on each other can be split up like this,
+// ECB and CTR.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use crate::error::IceError;
+use crate::icefast::Ice;
+use crate::mode::{Chain, Mode, Padding};
+
+/// Tuning for `encrypt_file` and friends.
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineOptions {
+    segment_len: usize,
+    in_flight: usize,
+    workers: usize,
+}
+
+impl Default for PipelineOptions {
+    /// 1 MiB segments, one worker per CPU and two segments per worker.
+    fn default() -> Self {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        PipelineOptions {
+            segment_len: 1 << 20,
+            in_flight: 2 * workers,
+            workers,
+        }
+    }
+}
+
+impl PipelineOptions {
+    /// Same as `PipelineOptions::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes per segment, rounded up to a whole number of blocks.
+    pub fn with_segment_len(mut self, bytes: usize) -> Self {
+        self.segment_len = bytes.max(1).checked_next_multiple_of(8).unwrap_or(usize::MAX & !7);
+        self
+    }
+
+    /// Segments allocated at once, which bounds the memory use.
+    pub fn with_in_flight(mut self, segments: usize) -> Self {
+        self.in_flight = segments.max(1);
+        self
+    }
+
+    /// Worker threads running the kernels.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+}
+
+/// Encrypt the file at `src` into `dst` in `mode`, returning the number of
+/// bytes.
+///
+/// In `Mode::Ecb` the file has to be a multiple of 8 bytes long, `Mode::Ctr`
+/// takes any length. `Mode::Cbc` fails with `InvalidInput`, each block
+/// depends on the one before it.
+pub fn encrypt_file(
+    ice: &Ice,
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    mode: Mode,
+    options: &PipelineOptions,
+) -> Result<u64, IceError> {
+    independent(mode)?;
+    crypt_file(ice, mode, src.as_ref(), dst.as_ref(), options, Chain::encrypt)
+}
+
+/// Decrypt the file at `src` into `dst`, see `encrypt_file`.
+pub fn decrypt_file(
+    ice: &Ice,
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    mode: Mode,
+    options: &PipelineOptions,
+) -> Result<u64, IceError> {
+    independent(mode)?;
+    crypt_file(ice, mode, src.as_ref(), dst.as_ref(), options, Chain::decrypt)
+}
+
+/// Encrypt everything `reader` yields into `writer`, see `encrypt_file`.
+pub fn encrypt_stream(
+    ice: &Ice,
+    reader: impl Read + Send,
+    writer: impl Write,
+    mode: Mode,
+    options: &PipelineOptions,
+) -> Result<u64, IceError> {
+    independent(mode)?;
+    crypt_stream(ice, mode, reader, writer, options, Chain::encrypt)
+}
+
+/// Decrypt everything `reader` yields into `writer`, see `encrypt_file`.
+pub fn decrypt_stream(
+    ice: &Ice,
+    reader: impl Read + Send,
+    writer: impl Write,
+    mode: Mode,
+    options: &PipelineOptions,
+) -> Result<u64, IceError> {
+    independent(mode)?;
+    crypt_stream(ice, mode, reader, writer, options, Chain::decrypt)
+}
+
+/*
+ * Segments are encrypted out of order, which only works when no block
+ * depends on the one before it.
+ */
+fn independent(mode: Mode) -> Result<(), IceError> {
+    match mode {
+        Mode::Cbc { .. } => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "CBC chains every block to the one before, it can't be pipelined",
+        )
+        .into()),
+        Mode::Ecb | Mode::Ctr { .. } => Ok(()),
+    }
+}
+
+/*
+ * Chain::encrypt or Chain::decrypt.
+ */
+type Crypt = fn(&mut Chain, &Ice, &mut [u8]);
+
+/*
+ * Whether the stream has to be whole blocks. CTR just stops the keystream
+ * where the data ends.
+ */
+fn whole_blocks(mode: Mode) -> bool {
+    !matches!(mode, Mode::Ctr { .. })
+}
+
+/*
+ * Run `crypt` over the segment at `offset`. Only the last segment can end
+ * on a partial block, and only in CTR, where the keystream is the same
+ * both ways.
+ */
+fn crypt_segment(ice: &Ice, mode: Mode, offset: u64, segment: &mut [u8], crypt: Crypt) {
+    let mut chain = Chain::resume(mode, offset / 8, [0; 8]);
+    let (blocks, tail) = segment.split_at_mut(segment.len() & !7);
+    crypt(&mut chain, ice, blocks);
+    if !tail.is_empty() {
+        let mut out = Vec::with_capacity(tail.len());
+        // CTR takes any length, there is nothing to fail
+        let _ = chain.encrypt_final(ice, Padding::None, tail, 0, &mut out);
+        tail.copy_from_slice(&out);
+    }
+}
+
+fn crypt_file(
+    ice: &Ice,
+    mode: Mode,
+    src: &Path,
+    dst: &Path,
+    options: &PipelineOptions,
+    crypt: Crypt,
+) -> Result<u64, IceError> {
+    let reader = File::open(src)?;
+    // Fail before creating the output if it can't work out anyway
+    let len = reader.metadata()?.len();
+    if whole_blocks(mode) && !len.is_multiple_of(8) {
+        return Err(IceError::BlockSize(len as usize));
+    }
+
+    let mut writer = BufWriter::new(File::create(dst)?);
+    let written = crypt_stream(ice, mode, reader, &mut writer, options, crypt)?;
+    writer.flush()?;
+    Ok(written)
+}
+
+/*
+ * A segment and where it starts in the stream.
+ */
+struct Segment {
+    offset: u64,
+    data: Vec<u8>,
+}
+
+/*
+ * Run `crypt` over the stream one segment at a time, each on a chain
+ * resumed at the segment's offset, for CTR to pick up the counter there.
+ */
+fn crypt_stream(
+    ice: &Ice,
+    mode: Mode,
+    reader: impl Read + Send,
+    mut writer: impl Write,
+    options: &PipelineOptions,
+    crypt: Crypt,
+) -> Result<u64, IceError> {
+    let (free_tx, free_rx) = sync_channel::<Vec<u8>>(options.in_flight);
+    let (work_tx, work_rx) = sync_channel::<Segment>(options.in_flight);
+    let (done_tx, done_rx) = sync_channel::<Segment>(options.in_flight);
+    for _ in 0..options.in_flight {
+        free_tx.send(vec![0; options.segment_len]).unwrap();
+    }
+    let work_rx = Arc::new(Mutex::new(work_rx));
+
+    std::thread::scope(|scope| {
+        let segment_len = options.segment_len;
+        let whole_blocks = whole_blocks(mode);
+        let read = scope
+            .spawn(move || read_segments(reader, segment_len, whole_blocks, free_rx, work_tx));
+
+        for _ in 0..options.workers {
+            let work_rx = Arc::clone(&work_rx);
+            let done_tx = done_tx.clone();
+            scope.spawn(move || loop {
+                let next = work_rx.lock().unwrap().recv();
+                let Ok(mut segment) = next else { break };
+                crypt_segment(ice, mode, segment.offset, &mut segment.data, crypt);
+                if done_tx.send(segment).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(done_tx);
+
+        // Dropping the channel ends on error unblocks the other threads
+        let written = write_segments(&mut writer, done_rx, free_tx);
+        let read = read.join().unwrap();
+        let written = written?;
+        read?;
+        Ok(written)
+    })
+}
+
+fn read_segments(
+    mut reader: impl Read,
+    segment_len: usize,
+    whole_blocks: bool,
+    free_rx: Receiver<Vec<u8>>,
+    work_tx: SyncSender<Segment>,
+) -> Result<(), IceError> {
+    let mut offset = 0;
+    // Stops early when the writer hangs up, it reports its own error
+    while let Ok(mut data) = free_rx.recv() {
+        data.resize(segment_len, 0);
+        let len = fill(&mut reader, &mut data)?;
+        if len == 0 {
+            break;
+        }
+        if whole_blocks && !len.is_multiple_of(8) {
+            return Err(IceError::BlockSize(offset as usize + len));
+        }
+
+        data.truncate(len);
+        if work_tx.send(Segment { offset, data }).is_err() {
+            break;
+        }
+        offset += len as u64;
+        if len < segment_len {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn write_segments(
+    writer: &mut impl Write,
+    done_rx: Receiver<Segment>,
+    free_tx: SyncSender<Vec<u8>>,
+) -> Result<u64, IceError> {
+    // Segments finish out of order, park them until their turn
+    let mut pending = BTreeMap::new();
+    let mut written = 0;
+    for segment in done_rx {
+        pending.insert(segment.offset, segment.data);
+        while let Some(data) = pending.remove(&written) {
+            writer.write_all(&data)?;
+            written += data.len() as u64;
+            // The reader may have finished and gone, that's fine
+            let _ = free_tx.send(data);
+        }
+    }
+    Ok(written)
+}
+
+/*
+ * Read until `buf` is full or the reader is exhausted.
+ */
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
@@ -0,0 +1,371 @@
+// std::io adapters that encrypt or decrypt as the data passes through.
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::error::IceError;
+use crate::icefast::Ice;
+use crate::mode::{Chain, Mode, Padding};
+
+/* Plaintext gathered before a run goes through the kernels */
+const RUN: usize = 16 * 1024;
+
+/// Encrypts everything written to it into the inner writer.
+///
+/// Whole blocks are buffered into runs for the wide kernels. The last
+/// block or two are held back until `finish` applies the padding, so
+/// `finish` has to be called, dropping the writer loses them.
+///
+/// An error from the inner writer leaves everything as it was, so the call
+/// can be retried, e.g. after `WouldBlock`.
+#[derive(Debug)]
+pub struct IceWriter<'a, W: Write> {
+    inner: W,
+    ice: &'a Ice,
+    chain: Chain,
+    padding: Padding,
+    pending: Vec<u8>,
+    // Ciphertext the inner writer hasn't taken yet
+    out: Vec<u8>,
+    written: usize,
+    total: u64,
+    finished: bool,
+}
+
+impl<'a, W: Write> IceWriter<'a, W> {
+    pub fn new(inner: W, ice: &'a Ice, mode: Mode, padding: Padding) -> Self {
+        IceWriter {
+            inner,
+            ice,
+            chain: Chain::new(mode),
+            padding,
+            pending: Vec::with_capacity(RUN + 16),
+            out: Vec::with_capacity(RUN + 16),
+            written: 0,
+            total: 0,
+            finished: false,
+        }
+    }
+
+    /// The inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// The inner writer, anything not written out by `finish` is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /*
+     * Encrypt everything that can't be affected by padding onto `out`.
+     */
+    fn seal(&mut self) {
+        let len = self.pending.len() - self.chain.hold_back(self.padding, self.pending.len());
+        self.chain.encrypt(self.ice, &mut self.pending[..len]);
+        self.out.extend(self.pending.drain(..len));
+    }
+
+    /*
+     * Hand `out` to the inner writer until it has taken all of it. Each
+     * byte is only encrypted once, a failed write is picked up again at
+     * `written` next time.
+     */
+    fn write_out(&mut self) -> io::Result<()> {
+        while self.written < self.out.len() {
+            match self.inner.write(&self.out[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(len) => self.written += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.out.clear();
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Encrypt the held back bytes with the padding and flush. Like `write`
+    /// it can be called again after an error, the padding is only applied
+    /// once. `into_inner` gets the inner writer back afterwards.
+    pub fn finish(&mut self) -> Result<(), IceError> {
+        if !self.finished {
+            self.seal();
+            // Left in place on error, so a retry fails the same way
+            self.chain
+                .encrypt_final(self.ice, self.padding, &self.pending, self.total, &mut self.out)?;
+            self.pending.clear();
+            self.finished = true;
+        }
+        self.write_out()?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for IceWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Nothing new is taken while a run is still on its way out, and
+        // nothing can fail once `buf` is taken
+        self.write_out()?;
+
+        let len = buf.len().min(RUN);
+        self.pending.extend_from_slice(&buf[..len]);
+        self.total += len as u64;
+        if self.pending.len() >= RUN {
+            self.seal();
+        }
+        Ok(len)
+    }
+
+    /// Writes out all but the held back bytes, see `IceWriter`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.seal();
+        self.write_out()?;
+        self.inner.flush()
+    }
+}
+
+/// Decrypts everything read from the inner reader.
+///
+/// Ciphertext is read in runs for the wide kernels. The last block or two
+/// are only decrypted once the inner reader hits EOF, which is when the
+/// padding is checked and stripped. Bad padding or a bad length comes out
+/// of `read` as an `InvalidData` error with the `IceError` inside.
+#[derive(Debug)]
+pub struct IceReader<'a, R: Read> {
+    inner: R,
+    ice: &'a Ice,
+    chain: Chain,
+    padding: Padding,
+    pending: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+    total: u64,
+    eof: bool,
+}
+
+impl<'a, R: Read> IceReader<'a, R> {
+    pub fn new(inner: R, ice: &'a Ice, mode: Mode, padding: Padding) -> Self {
+        IceReader {
+            inner,
+            ice,
+            chain: Chain::new(mode),
+            padding,
+            pending: Vec::with_capacity(RUN + 16),
+            plain: Vec::with_capacity(RUN + 16),
+            pos: 0,
+            total: 0,
+            eof: false,
+        }
+    }
+
+    /// The inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// The inner reader, anything already read from it but not returned is
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /*
+     * Read the next run and decrypt whatever padding can't affect, or the
+     * rest at EOF. May leave `plain` empty before EOF when the run was
+     * held back whole.
+     */
+    fn fill(&mut self) -> io::Result<()> {
+        self.plain.clear();
+        self.pos = 0;
+
+        let start = self.pending.len();
+        self.pending.resize(start + RUN, 0);
+        let read = loop {
+            match self.inner.read(&mut self.pending[start..]) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                read => break read,
+            }
+        };
+        let read = match read {
+            Ok(read) => read,
+            Err(err) => {
+                self.pending.truncate(start);
+                return Err(err);
+            }
+        };
+        self.pending.truncate(start + read);
+        self.total += read as u64;
+
+        if read == 0 {
+            self.eof = true;
+            let tail = std::mem::take(&mut self.pending);
+            self.chain
+                .decrypt_final(self.ice, self.padding, &tail, self.total, &mut self.plain)?;
+            return Ok(());
+        }
+
+        self.chain
+            .decrypt_ready(self.ice, self.padding, &mut self.pending, &mut self.plain);
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for IceReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.eof || buf.is_empty() {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let len = buf.len().min(self.plain.len() - self.pos);
+        buf[..len].copy_from_slice(&self.plain[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Decrypts a seekable reader with random access by plaintext offset.
+///
+/// Every block only depends on its position, the ciphertext block before
+/// it for CBC, so reading after a seek decrypts just the blocks the read
+/// touches. The padded end is decrypted once up front, which also gives
+/// the plaintext length and checks the padding.
+#[derive(Debug)]
+pub struct IceSeekReader<'a, R: Read + Seek> {
+    inner: R,
+    ice: &'a Ice,
+    mode: Mode,
+    // Ciphertext offset of the held back end, a whole number of blocks
+    tail_start: u64,
+    tail: Vec<u8>,
+    len: u64,
+    pos: u64,
+    plain: Vec<u8>,
+    plain_start: u64,
+}
+
+impl<'a, R: Read + Seek> IceSeekReader<'a, R> {
+    pub fn new(inner: R, ice: &'a Ice, mode: Mode, padding: Padding) -> Result<Self, IceError> {
+        let mut reader = IceSeekReader {
+            inner,
+            ice,
+            mode,
+            tail_start: 0,
+            tail: Vec::new(),
+            len: 0,
+            pos: 0,
+            plain: Vec::new(),
+            plain_start: 0,
+        };
+
+        let cipher_len = reader.inner.seek(SeekFrom::End(0))?;
+        let held = Chain::new(mode).hold_back_decrypt(padding, cipher_len as usize);
+        reader.tail_start = cipher_len - held as u64;
+
+        let mut chain = reader.chain_at(reader.tail_start / 8)?;
+        let mut cipher = vec![0; held];
+        reader.inner.seek(SeekFrom::Start(reader.tail_start))?;
+        reader.inner.read_exact(&mut cipher)?;
+        chain.decrypt_final(ice, padding, &cipher, cipher_len, &mut reader.tail)?;
+        reader.len = reader.tail_start + reader.tail.len() as u64;
+        Ok(reader)
+    }
+
+    /// Length of the plaintext.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the plaintext is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// The inner reader, at an unspecified position.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn chain_at(&mut self, block: u64) -> io::Result<Chain> {
+        let prev = match self.mode {
+            Mode::Cbc { iv } if block == 0 => iv,
+            Mode::Cbc { .. } => {
+                let mut prev = [0; 8];
+                self.inner.seek(SeekFrom::Start(8 * (block - 1)))?;
+                self.inner.read_exact(&mut prev)?;
+                prev
+            }
+            _ => [0; 8],
+        };
+        Ok(Chain::resume(self.mode, block, prev))
+    }
+
+    /*
+     * Decrypt the blocks a read of `want` bytes at `pos` touches, at most
+     * a run of them and never into the tail.
+     */
+    fn fill(&mut self, want: usize) -> io::Result<()> {
+        let start = self.pos - self.pos % 8;
+        let end = (self.pos + want as u64)
+            .next_multiple_of(8)
+            .min(start + RUN as u64)
+            .min(self.tail_start);
+
+        let mut chain = self.chain_at(start / 8)?;
+        self.plain.resize((end - start) as usize, 0);
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut self.plain)?;
+        chain.decrypt(self.ice, &mut self.plain);
+        self.plain_start = start;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for IceSeekReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let (plain, offset) = if self.pos >= self.tail_start {
+            (&self.tail, self.pos - self.tail_start)
+        } else {
+            let decrypted = self.plain_start..self.plain_start + self.plain.len() as u64;
+            if !decrypted.contains(&self.pos) {
+                self.fill(buf.len())?;
+            }
+            (&self.plain, self.pos - self.plain_start)
+        };
+
+        let plain = &plain[offset as usize..];
+        let len = buf.len().min(plain.len());
+        buf[..len].copy_from_slice(&plain[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for IceSeekReader<'_, R> {
+    /// Only moves the position, nothing is read until the next `read`.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        let Some(pos) = pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        };
+        self.pos = pos;
+        Ok(pos)
+    }
+}
@@ -0,0 +1,159 @@
+// Testing for the tokio adapters over in-memory duplex streams
+#![cfg(feature = "tokio")]
+
+mod common;
+
+#[path = "../src/stream.rs"]
+#[allow(dead_code)]
+mod stream;
+
+#[path = "../src/async_io.rs"]
+#[allow(dead_code)]
+mod async_io;
+
+use std::io::Write;
+
+use common::{error, icefast, mode, text, IV, KEY8, MODES};
+use async_io::{AsyncIceReader, AsyncIceWriter};
+use error::IceError;
+use mode::{Mode, Padding};
+use stream::IceWriter;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+fn encrypt(ice: &icefast::Ice, mode: Mode, padding: Padding, plain: &[u8]) -> Vec<u8> {
+    let mut writer = IceWriter::new(Vec::new(), ice, mode, padding);
+    writer.write_all(plain).unwrap();
+    writer.finish().unwrap();
+    writer.into_inner()
+}
+
+/*
+ * Writes `plain` through an AsyncIceWriter in `piece` sized writes into a
+ * duplex with a small buffer, so both ends keep hitting Pending, and
+ * reads it back out of the other end through an AsyncIceReader.
+ */
+async fn roundtrip(
+    ice: &icefast::Ice,
+    mode: Mode,
+    padding: Padding,
+    plain: &[u8],
+    piece: usize,
+) -> (Vec<u8>, std::io::Result<Vec<u8>>) {
+    let (client, server) = duplex(61);
+    let (cipher_tx, cipher_rx) = duplex(1 << 20);
+
+    let write = async {
+        let mut writer = AsyncIceWriter::new(client, ice, mode, padding);
+        for chunk in plain.chunks(piece) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+    };
+
+    // Keep a copy of the ciphertext on the way through
+    let tee = async {
+        let (mut server, mut cipher_tx) = (server, cipher_tx);
+        let mut cipher = Vec::new();
+        let mut buf = [0; 37];
+        loop {
+            let len = server.read(&mut buf).await.unwrap();
+            if len == 0 {
+                break;
+            }
+            cipher.extend_from_slice(&buf[..len]);
+            cipher_tx.write_all(&buf[..len]).await.unwrap();
+        }
+        cipher_tx.shutdown().await.unwrap();
+        cipher
+    };
+
+    let read = async {
+        let mut reader = AsyncIceReader::new(cipher_rx, ice, mode, padding);
+        let mut decrypted = Vec::new();
+        let mut buf = [0; 29];
+        loop {
+            let len = reader.read(&mut buf).await?;
+            if len == 0 {
+                return Ok(decrypted);
+            }
+            decrypted.extend_from_slice(&buf[..len]);
+        }
+    };
+
+    let ((), cipher, decrypted) = tokio::join!(write, tee, read);
+    (cipher, decrypted)
+}
+
+#[tokio::test]
+async fn async_roundtrip_matches_sync() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for mode in MODES {
+        for padding in [Padding::None, Padding::Pkcs7, Padding::Stealing] {
+            for len in [0usize, 8, 21, 8 * 2500, 8 * 2500 + 3] {
+                if padding == Padding::None && !len.is_multiple_of(8) && mode != MODES[2] {
+                    continue;
+                }
+                let plain = text(len);
+                let expect = encrypt(&test_ice, mode, padding, &plain);
+                for piece in [1, 13, 100_000] {
+                    let msg = format!("{:?} {:?} {} bytes in {}s", mode, padding, len, piece);
+                    let (cipher, decrypted) =
+                        roundtrip(&test_ice, mode, padding, &plain, piece).await;
+                    assert_eq!(cipher, expect, "{}", msg);
+                    assert_eq!(decrypted.expect(&msg), plain, "{}", msg);
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn async_reader_bad_padding() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let other_ice = icefast::Ice::new(0, &[0; 8]);
+    let mode = Mode::Cbc { iv: IV };
+    let cipher = encrypt(&test_ice, mode, Padding::Pkcs7, &text(29));
+
+    let mut reader = AsyncIceReader::new(&cipher[..], &other_ice, mode, Padding::Pkcs7);
+    let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let err = err.get_ref().unwrap().downcast_ref::<IceError>().unwrap();
+    assert!(matches!(err, IceError::BadPadding));
+}
+
+#[tokio::test]
+async fn async_writer_bad_length() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mut writer = AsyncIceWriter::new(Vec::new(), &test_ice, Mode::Ecb, Padding::None);
+    writer.write_all(&text(13)).await.unwrap();
+    // Retrying doesn't make the tail disappear
+    for _ in 0..2 {
+        let err = writer.shutdown().await.unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<IceError>().unwrap();
+        assert!(matches!(err, IceError::BlockSize(13)));
+    }
+}
+
+#[tokio::test]
+async fn async_adapters_in_spawned_tasks() {
+    let test_ice = std::sync::Arc::new(icefast::Ice::new(0, &KEY8));
+    let mode = Mode::Ctr { nonce: IV };
+    let plain = text(8 * 3000 + 5);
+    let (client, server) = duplex(64);
+
+    let mut writer = AsyncIceWriter::new(client, test_ice.clone(), mode, Padding::None);
+    let sent = plain.clone();
+    let write = tokio::spawn(async move {
+        writer.write_all(&sent).await.unwrap();
+        writer.shutdown().await.unwrap();
+    });
+    let mut reader = AsyncIceReader::new(server, test_ice.clone(), mode, Padding::None);
+    let read = tokio::spawn(async move {
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).await.unwrap();
+        decrypted
+    });
+
+    write.await.unwrap();
+    assert_eq!(read.await.unwrap(), plain);
+}
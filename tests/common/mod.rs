@@ -0,0 +1,31 @@
+// Shared by the tests of the modules built on icefast: the modules they all
+// include, and the fixtures they all use.
+//
+// The modules refer to each other as `crate::icefast` and so on, so a test
+// brings the ones it needs to its root with `use common::{error, icefast};`
+// and includes the module under test by path next to them.
+#![allow(dead_code)]
+
+#[path = "../../src/icefast.rs"]
+pub mod icefast;
+
+#[path = "../../src/error.rs"]
+pub mod error;
+
+#[path = "../../src/mode.rs"]
+pub mod mode;
+
+use mode::Mode;
+
+pub static KEY8: [u8; 8] = [0x51, 0xF3, 0x0F, 0x11, 0x04, 0x24, 0x6A, 0x00];
+pub static MAC_KEY8: [u8; 8] = [0x0F, 0x1E, 0x2D, 0x3C, 0x4B, 0x5A, 0x69, 0x78];
+pub static IV: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+pub const MODES: [Mode; 3] = [Mode::Ecb, Mode::Cbc { iv: IV }, Mode::Ctr { nonce: IV }];
+
+/*
+ * Plaintext that doesn't repeat within a block or a run.
+ */
+pub fn text(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+}
@@ -0,0 +1,78 @@
+// Testing for the optional compression stage
+#![cfg(any(feature = "deflate", feature = "zstd"))]
+
+mod common;
+
+#[path = "../src/compress.rs"]
+mod compress;
+
+use common::{error, icefast, KEY8};
+use compress::{compress_and_encrypt, decrypt_and_decompress, Compression};
+use error::IceError;
+
+fn algorithms() -> Vec<Compression> {
+    vec![
+        #[cfg(feature = "deflate")]
+        Compression::Deflate,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+    ]
+}
+
+#[test]
+fn compress_roundtrip_level0() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let text = "abcdefgh".repeat(1000);
+    for compression in algorithms() {
+        let data = compress_and_encrypt(&test_ice, text.as_bytes(), compression).unwrap();
+        assert!(data.len().is_multiple_of(8));
+        assert!(data.len() < text.len());
+        let plain = decrypt_and_decompress(&test_ice, &data).unwrap();
+        assert_eq!(plain, text.as_bytes());
+    }
+}
+
+#[test]
+fn compress_roundtrip_odd_length() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let text = "not a multiple of eight";
+    for compression in algorithms() {
+        let data = compress_and_encrypt(&test_ice, text.as_bytes(), compression).unwrap();
+        let plain = decrypt_and_decompress(&test_ice, &data).unwrap();
+        assert_eq!(plain, text.as_bytes());
+    }
+}
+
+#[test]
+fn compress_roundtrip_empty() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for compression in algorithms() {
+        let data = compress_and_encrypt(&test_ice, &[], compression).unwrap();
+        let plain = decrypt_and_decompress(&test_ice, &data).unwrap();
+        assert!(plain.is_empty());
+    }
+}
+
+#[test]
+fn decompress_wrong_key() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let other_ice = icefast::Ice::new(0, &[0; 8]);
+    let text = "abcdefgh".repeat(100);
+    for compression in algorithms() {
+        let data = compress_and_encrypt(&test_ice, text.as_bytes(), compression).unwrap();
+        assert!(decrypt_and_decompress(&other_ice, &data).is_err());
+    }
+}
+
+#[test]
+fn decompress_bad_length() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    assert!(matches!(
+        decrypt_and_decompress(&test_ice, &[0; 12]),
+        Err(IceError::BlockSize(12))
+    ));
+    assert!(matches!(
+        decrypt_and_decompress(&test_ice, &[0; 16]),
+        Err(IceError::BadHeader)
+    ));
+}
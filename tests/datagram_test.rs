@@ -0,0 +1,162 @@
+// Testing for replay protected datagrams over a local UDP socket pair
+mod common;
+
+#[path = "../src/datagram.rs"]
+mod datagram;
+
+use std::net::UdpSocket;
+
+use common::{error, icefast, mode, text, IV, KEY8, MAC_KEY8};
+use datagram::{DatagramOpener, DatagramSealer, MAX_PAYLOAD_LEN, REPLAY_WINDOW};
+use error::IceError;
+
+fn socket_pair() -> (UdpSocket, UdpSocket) {
+    let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+    a.connect(b.local_addr().unwrap()).unwrap();
+    b.connect(a.local_addr().unwrap()).unwrap();
+    (a, b)
+}
+
+fn recv(socket: &UdpSocket) -> Vec<u8> {
+    let mut buf = [0; 2048];
+    let len = socket.recv(&mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+#[test]
+fn datagrams_over_udp() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let mac_ice = icefast::Ice::new(1, &MAC_KEY8);
+    let (client, server) = socket_pair();
+    for tag_len in [4, 8] {
+        let mut sealer = DatagramSealer::new(&test_ice, &mac_ice, IV).with_tag_len(tag_len);
+        let mut opener = DatagramOpener::new(&test_ice, &mac_ice, IV).with_tag_len(tag_len);
+
+        let packets: Vec<_> = [0, 1, 13, 64, 1200]
+            .map(|len| sealer.seal(&text(len)).unwrap())
+            .to_vec();
+        assert_eq!(packets[2].len(), 8 + 13 + tag_len);
+        assert_eq!(sealer.sequence(), 5);
+
+        // Reordered, with a duplicate in between
+        for i in [1, 0, 3, 3, 4, 2] {
+            client.send(&packets[i]).unwrap();
+        }
+        let mut received = Vec::new();
+        for _ in 0..6 {
+            match opener.open(&recv(&server)) {
+                Ok((seq, payload)) => received.push((seq, payload.len())),
+                Err(IceError::Replayed(3)) => received.push((3, usize::MAX)),
+                Err(err) => panic!("{}", err),
+            }
+        }
+        let expect = [(1, 1), (0, 0), (3, 64), (3, usize::MAX), (4, 1200), (2, 13)];
+        assert_eq!(received, expect);
+    }
+}
+
+#[test]
+fn replay_window() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mac_ice = icefast::Ice::new(0, &MAC_KEY8);
+    let mut sealer = DatagramSealer::new(&test_ice, &mac_ice, IV);
+    let mut opener = DatagramOpener::new(&test_ice, &mac_ice, IV);
+    let packets: Vec<_> = (0..300).map(|_| sealer.seal(b"state").unwrap()).collect();
+
+    // Jump ahead, then fill in what is still inside the window
+    let newest = 150;
+    opener.open(&packets[newest]).unwrap();
+    let oldest = newest - REPLAY_WINDOW as usize + 1;
+    for seq in (oldest..newest).rev() {
+        assert_eq!(opener.open(&packets[seq]).unwrap().0, seq as u64);
+    }
+    for seq in [oldest - 1, 0, oldest, newest] {
+        let result = opener.open(&packets[seq]);
+        assert!(matches!(result, Err(IceError::Replayed(s)) if s == seq as u64));
+    }
+
+    // A big jump forgets everything behind it
+    opener.open(&packets[299]).unwrap();
+    assert!(matches!(
+        opener.open(&packets[151]),
+        Err(IceError::Replayed(151))
+    ));
+    assert!(opener.open(&packets[240]).is_ok());
+}
+
+#[test]
+fn forged_datagrams() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mac_ice = icefast::Ice::new(0, &MAC_KEY8);
+    let mut sealer = DatagramSealer::new(&test_ice, &mac_ice, IV);
+    let mut opener = DatagramOpener::new(&test_ice, &mac_ice, IV);
+    let packet = sealer.seal(b"move 3 4").unwrap();
+
+    // Any flipped bit, sequence number included, is caught
+    for i in 0..packet.len() {
+        let mut forged = packet.clone();
+        forged[i] ^= 0x10;
+        assert!(
+            matches!(opener.open(&forged), Err(IceError::BadMac)),
+            "byte {}",
+            i
+        );
+    }
+    assert!(matches!(
+        opener.open(&packet[..11]),
+        Err(IceError::BadHeader)
+    ));
+
+    // None of that moved the window
+    assert_eq!(opener.open(&packet).unwrap(), (0, b"move 3 4".to_vec()));
+
+    // Nor can the other direction, with its own nonce, be reflected back
+    let mut reply = DatagramSealer::new(&test_ice, &mac_ice, [9; 8]);
+    reply.seal(b"ok").unwrap();
+    assert!(matches!(
+        opener.open(&reply.seal(b"ok").unwrap()),
+        Err(IceError::BadMac)
+    ));
+}
+
+#[test]
+fn oversized_payload() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mac_ice = icefast::Ice::new(0, &MAC_KEY8);
+    let mut sealer = DatagramSealer::new(&test_ice, &mac_ice, IV);
+    let mut opener = DatagramOpener::new(&test_ice, &mac_ice, IV);
+
+    // One block more would reuse the keystream of the next packet
+    assert!(matches!(
+        sealer.seal(&text(MAX_PAYLOAD_LEN + 8)),
+        Err(IceError::FrameTooLarge(len)) if len == MAX_PAYLOAD_LEN + 8
+    ));
+    assert_eq!(sealer.sequence(), 0);
+
+    let plain = text(MAX_PAYLOAD_LEN);
+    let packet = sealer.seal(&plain).unwrap();
+    assert_eq!(opener.open(&packet).unwrap(), (0, plain));
+}
+
+#[test]
+fn owned_keys_across_threads() {
+    use std::sync::Arc;
+
+    // Arc keys, so the sealer can move to a thread of its own
+    let test_ice = Arc::new(icefast::Ice::new(1, &KEY8));
+    let mac_ice = Arc::new(icefast::Ice::new(1, &MAC_KEY8));
+    let mut sealer = DatagramSealer::new(test_ice.clone(), mac_ice.clone(), IV);
+    let mut opener = DatagramOpener::new(test_ice, mac_ice, IV);
+
+    let (client, server) = socket_pair();
+    let send = std::thread::spawn(move || {
+        for len in [0, 13, 1200] {
+            client.send(&sealer.seal(&text(len)).unwrap()).unwrap();
+        }
+    });
+    send.join().unwrap();
+    for (seq, len) in [(0, 0), (1, 13), (2, 1200)] {
+        assert_eq!(opener.open(&recv(&server)).unwrap(), (seq, text(len)));
+    }
+}
@@ -0,0 +1,173 @@
+// Testing for the framed message codec
+mod common;
+
+#[path = "../src/frame.rs"]
+mod frame;
+
+use std::net::{TcpListener, TcpStream};
+
+use common::{error, icefast, mode, text, IV, KEY8, MAC_KEY8, MODES};
+use error::IceError;
+use frame::FrameCodec;
+use mode::{Mode, Padding};
+
+fn messages() -> Vec<Vec<u8>> {
+    [0, 1, 8, 13, 100, 70_000].map(text).to_vec()
+}
+
+#[test]
+fn frames_over_loopback() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let mac_ice = icefast::Ice::new(1, &MAC_KEY8);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    for mode in MODES {
+        for with_mac in [false, true] {
+            let new_codec = || {
+                let codec = FrameCodec::new(&test_ice, mode, Padding::Pkcs7);
+                if with_mac {
+                    codec.with_mac(&mac_ice)
+                } else {
+                    codec
+                }
+            };
+            let codec = new_codec();
+            let received = std::thread::scope(|scope| {
+                let server = scope.spawn(|| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut received = Vec::new();
+                    while let Some(msg) = codec.read_frame(&mut stream).unwrap() {
+                        received.push(msg);
+                    }
+                    received
+                });
+
+                let mut client = TcpStream::connect(addr).unwrap();
+                let mut codec = new_codec();
+                for msg in messages() {
+                    codec.write_frame(&mut client, &msg).unwrap();
+                }
+                drop(client);
+                server.join().unwrap()
+            });
+            assert_eq!(received, messages(), "{:?} mac {}", mode, with_mac);
+        }
+    }
+}
+
+#[test]
+fn decode_partial_frames() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mut codec = FrameCodec::new(&test_ice, Mode::Ctr { nonce: IV }, Padding::None);
+    let mut stream = Vec::new();
+    for msg in messages() {
+        codec.encode(&msg, &mut stream).unwrap();
+    }
+
+    // Bytes trickle in, a frame comes out only once it's all there
+    let mut buf = Vec::new();
+    let mut received = Vec::new();
+    for chunk in stream.chunks(333) {
+        buf.extend_from_slice(chunk);
+        while let Some((msg, len)) = codec.decode(&buf).unwrap() {
+            buf.drain(..len);
+            received.push(msg);
+        }
+    }
+    assert!(buf.is_empty());
+    assert_eq!(received, messages());
+}
+
+#[test]
+fn fresh_iv_per_frame() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for mode in [Mode::Cbc { iv: IV }, Mode::Ctr { nonce: IV }] {
+        let mut codec = FrameCodec::new(&test_ice, mode, Padding::Pkcs7);
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        codec.encode(&text(16), &mut first).unwrap();
+        codec.encode(&text(16), &mut second).unwrap();
+        assert_ne!(first[4..12], second[4..12], "{:?}", mode);
+        assert_ne!(first[12..], second[12..], "{:?}", mode);
+    }
+}
+
+#[test]
+fn reject_malformed_frames() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mac_ice = icefast::Ice::new(0, &MAC_KEY8);
+    let mode = Mode::Cbc { iv: IV };
+    let plain = FrameCodec::new(&test_ice, mode, Padding::Pkcs7).with_max_frame_len(1024);
+    let mut signed = FrameCodec::new(&test_ice, mode, Padding::Pkcs7)
+        .with_max_frame_len(1024)
+        .with_mac(&mac_ice);
+
+    // Too long is refused on the prefix alone
+    let result = plain.decode(&[0, 0, 4, 1]);
+    assert!(matches!(result, Err(IceError::FrameTooLarge(1025))));
+    let result = signed.encode(&text(2000), &mut Vec::new());
+    assert!(matches!(result, Err(IceError::FrameTooLarge(_))));
+
+    // Shorter than the IV and tag
+    let result = signed.decode(&[0, 0, 0, 12, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    assert!(matches!(result, Err(IceError::BadHeader)));
+
+    let mut frame = Vec::new();
+    signed.encode(&text(20), &mut frame).unwrap();
+    let last = frame.len() - 9;
+    frame[last] ^= 1;
+    assert!(matches!(signed.decode(&frame), Err(IceError::BadMac)));
+    assert!(matches!(plain.decode(&frame[..frame.len() - 8]), Ok(None)));
+
+    // Without a tag only the padding or block size gives it away
+    let mut frame = [0, 0, 0, 20].to_vec();
+    frame.extend_from_slice(&text(20));
+    assert!(matches!(plain.decode(&frame), Err(IceError::BlockSize(12))));
+
+    // Whatever arrives, decoding fails cleanly
+    for len in 0..64 {
+        for seed in 0..8 {
+            let mut frame = (len as u32).to_be_bytes().to_vec();
+            frame.extend((0..len).map(|i| (i * 31 + seed * 17) as u8));
+            for codec in [&plain, &signed] {
+                let _ = codec.decode(&frame);
+                let _ = codec.read_frame(&mut &frame[..]);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "codec")]
+#[tokio::test]
+async fn codec_over_loopback() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    // Owned keys, so both ends can run as tasks of their own
+    let test_ice = std::sync::Arc::new(icefast::Ice::new(1, &KEY8));
+    let mac_ice = std::sync::Arc::new(icefast::Ice::new(1, &MAC_KEY8));
+    let new_codec = || {
+        FrameCodec::new(test_ice.clone(), Mode::Cbc { iv: IV }, Padding::Pkcs7)
+            .with_mac(mac_ice.clone())
+    };
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let send_codec = new_codec();
+    let codec = new_codec();
+    let send = tokio::spawn(async move {
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut framed = FramedWrite::new(stream, send_codec);
+        for msg in messages() {
+            framed.send(&msg[..]).await.unwrap();
+        }
+    });
+    let receive = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let framed = FramedRead::new(stream, codec);
+        framed.map(Result::unwrap).collect::<Vec<_>>().await
+    });
+
+    send.await.unwrap();
+    assert_eq!(receive.await.unwrap(), messages());
+}
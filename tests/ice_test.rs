@@ -33,7 +33,7 @@ fn decrypt_level0() {
     test_ice.key_set(&ice_key);
 
     let expect_text: String = "abcdefgh".to_string();
-    let ciphertext = vec![195, 233, 103, 103, 181, 234, 50, 163];
+    let ciphertext = [195, 233, 103, 103, 181, 234, 50, 163];
 
     let mut ctext = [0; 8];
     let mut ptext = [0; 8];
@@ -77,7 +77,7 @@ fn decrypt_level1() {
     test_ice.key_set(&ice_key);
 
     let expect_text: String = "abcdefgh".to_string();
-    let ciphertext = vec![49, 188, 85, 204, 107, 67, 206, 70];
+    let ciphertext = [49, 188, 85, 204, 107, 67, 206, 70];
 
     let mut ctext = [0; 8];
     let mut ptext = [0; 8];
@@ -121,7 +121,7 @@ fn decrypt_level2() {
     test_ice.key_set(&ice_key);
 
     let expect_text: String = "abcdefgh".to_string();
-    let ciphertext = vec![234, 6, 99, 4, 147, 138, 221, 23];
+    let ciphertext = [234, 6, 99, 4, 147, 138, 221, 23];
 
     let mut ctext = [0; 8];
     let mut ptext = [0; 8];
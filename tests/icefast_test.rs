@@ -174,3 +174,488 @@ fn decrypt_16_fast_par_level2() {
     let plaintext = String::from_utf8(data.to_vec()).unwrap();
     assert_eq!(plaintext, EXPECT_TEXT_16);
 }
+
+#[test]
+fn kernels_match_scalar() {
+    // 1003 blocks exercise every kernel's full chunks plus the 16 and 8 byte tails.
+    let text = EXPECT_TEXT_8.repeat(1003);
+    for (level, key) in [(0, &KEY8[..]), (1, &KEY8[..]), (2, &KEY16[..])] {
+        let mut scalar_ice = icefast::Ice::new(level, key);
+        scalar_ice.set_kernel(icefast::Kernel::Scalar);
+        let mut expect = text.as_bytes().to_owned();
+        scalar_ice.encrypt(&mut expect);
+
+        for kernel in [
+            icefast::Kernel::Avx2,
+            icefast::Kernel::Avx512,
+            icefast::Kernel::Bitsliced,
+            icefast::Kernel::LargeTable,
+        ] {
+            if !kernel.is_supported() {
+                continue;
+            }
+            let mut test_ice = icefast::Ice::new(level, key);
+            test_ice.set_kernel(kernel);
+            assert_eq!(test_ice.kernel(), kernel);
+
+            let mut data = text.as_bytes().to_owned();
+            test_ice.encrypt(&mut data);
+            assert_eq!(data, expect, "{:?} encrypt level {}", kernel, level);
+            test_ice.decrypt(&mut data);
+            assert_eq!(data, text.as_bytes(), "{:?} decrypt level {}", kernel, level);
+
+            test_ice.encrypt_par(&mut data);
+            assert_eq!(data, expect, "{:?} encrypt_par level {}", kernel, level);
+            test_ice.decrypt_par(&mut data);
+            assert_eq!(data, text.as_bytes(), "{:?} decrypt_par level {}", kernel, level);
+        }
+    }
+}
+
+// xorshift64, enough to get varied keys and data without a rand dependency
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+fn random_bytes(state: &mut u64, len: usize) -> Vec<u8> {
+    (0..len).map(|_| xorshift(state) as u8).collect()
+}
+
+#[test]
+fn bitsliced_matches_scalar_random_keys() {
+    let mut state = 0x9e3779b97f4a7c15;
+    for level in 0..4 {
+        for len in [8, 64, 512, 520, 1024 + 88] {
+            let key = random_bytes(&mut state, 8 * level.max(1));
+            let text = random_bytes(&mut state, len);
+
+            let mut scalar_ice = icefast::Ice::new(level, &key);
+            scalar_ice.set_kernel(icefast::Kernel::Scalar);
+            let mut expect = text.clone();
+            scalar_ice.encrypt(&mut expect);
+
+            let mut test_ice = icefast::Ice::new(level, &key);
+            test_ice.set_kernel(icefast::Kernel::Bitsliced);
+            let mut data = text.clone();
+            test_ice.encrypt(&mut data);
+            assert_eq!(data, expect, "level {} len {}", level, len);
+            test_ice.decrypt(&mut data);
+            assert_eq!(data, text, "level {} len {}", level, len);
+        }
+    }
+}
+
+#[test]
+fn encrypt_n_widths_match() {
+    let text = EXPECT_TEXT_16.repeat(32);
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let mut expect = text.as_bytes().to_owned();
+    test_ice.encrypt_n::<1>(&mut expect);
+
+    let mut data = text.as_bytes().to_owned();
+    test_ice.encrypt_n::<2>(&mut data);
+    assert_eq!(data, expect);
+    test_ice.decrypt_n::<4>(&mut data);
+    assert_eq!(data, text.as_bytes());
+    test_ice.encrypt_n::<8>(&mut data);
+    assert_eq!(data, expect);
+    test_ice.decrypt_n::<1>(&mut data);
+    assert_eq!(data, text.as_bytes());
+}
+
+#[test]
+fn generic_loop_level3_roundtrip() {
+    // Level 3 (48 rounds) has no unrolled kernel and runs the generic loop.
+    let key = KEY8.repeat(3);
+    let test_ice = icefast::Ice::new(3, &key);
+    let mut data = EXPECT_TEXT_16.repeat(5).as_bytes().to_owned();
+    test_ice.encrypt(&mut data);
+    assert_ne!(data, EXPECT_TEXT_16.repeat(5).as_bytes());
+    test_ice.decrypt(&mut data);
+    assert_eq!(data, EXPECT_TEXT_16.repeat(5).as_bytes());
+}
+
+#[test]
+fn key_set_matches_bitwise_random_keys() {
+    let mut state = 0x2545f4914f6cdd1d;
+    for level in 0..5 {
+        let mut fast_ice = icefast::Ice::new(level, &vec![0; 8 * level.max(1)]);
+        let mut bitwise_ice = fast_ice.clone();
+        for _ in 0..1000 {
+            let key = random_bytes(&mut state, 8 * level.max(1));
+            fast_ice.key_set(&key);
+            bitwise_ice.key_set_bitwise(&key);
+            assert_eq!(fast_ice.key.keysched, bitwise_ice.key.keysched, "level {}", level);
+        }
+    }
+}
+
+#[test]
+fn decsched_is_reversed_keysched() {
+    for (level, key) in [(0, &KEY8[..]), (1, &KEY8[..]), (2, &KEY16[..])] {
+        let mut test_ice = icefast::Ice::new(level, key);
+        let mut reversed = test_ice.key.keysched.clone();
+        reversed.reverse();
+        assert_eq!(test_ice.key.decsched, reversed);
+
+        test_ice.key_set_bitwise(&key.iter().map(|b| b ^ 0x5a).collect::<Vec<_>>());
+        let mut reversed = test_ice.key.keysched.clone();
+        reversed.reverse();
+        assert_eq!(test_ice.key.decsched, reversed);
+    }
+}
+
+#[test]
+fn encrypt_many_matches_per_message() {
+    let mut state = 0x9e3779b97f4a7c15;
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    // Mostly small messages with a few larger than a batch mixed in.
+    let messages: Vec<Vec<u8>> = (0..3000)
+        .map(|i| {
+            let blocks = if i % 500 == 7 { 3000 } else { xorshift(&mut state) as usize % 9 };
+            random_bytes(&mut state, blocks * 8)
+        })
+        .collect();
+    let expect: Vec<Vec<u8>> = messages
+        .iter()
+        .map(|msg| {
+            let mut msg = msg.clone();
+            test_ice.encrypt(&mut msg);
+            msg
+        })
+        .collect();
+
+    let mut data = messages.clone();
+    let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|msg| msg.as_mut_slice()).collect();
+    test_ice.encrypt_many(&mut bufs);
+    assert_eq!(data, expect);
+    let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|msg| msg.as_mut_slice()).collect();
+    test_ice.decrypt_many(&mut bufs);
+    assert_eq!(data, messages);
+
+    let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|msg| msg.as_mut_slice()).collect();
+    test_ice.encrypt_many_par(&mut bufs);
+    assert_eq!(data, expect);
+    let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|msg| msg.as_mut_slice()).collect();
+    test_ice.decrypt_many_par(&mut bufs);
+    assert_eq!(data, messages);
+}
+
+#[test]
+#[should_panic(expected = "Data must be a multiple of 8 bytes")]
+fn encrypt_many_bad_length() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mut first = [0u8; 16];
+    let mut second = [0u8; 12];
+    test_ice.encrypt_many(&mut [&mut first[..], &mut second[..]]);
+}
+
+#[test]
+fn encrypt_vectored_matches_encrypt() {
+    use std::io::IoSliceMut;
+
+    let mut state = 0x2545f4914f6cdd1d;
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for round in 0..200 {
+        let blocks = xorshift(&mut state) as usize % 64 + if round % 50 == 0 { 4000 } else { 0 };
+        let plain = random_bytes(&mut state, blocks * 8);
+        let mut expect = plain.clone();
+        test_ice.encrypt(&mut expect);
+
+        // Cut at random points, tiny and empty fragments included
+        let mut cuts: Vec<usize> = (0..xorshift(&mut state) % 12)
+            .map(|_| xorshift(&mut state) as usize % (plain.len() + 1))
+            .collect();
+        cuts.extend([0, plain.len()]);
+        cuts.sort();
+        let mut frags: Vec<Vec<u8>> = cuts.windows(2).map(|cut| plain[cut[0]..cut[1]].to_vec()).collect();
+
+        let mut bufs: Vec<IoSliceMut> = frags.iter_mut().map(|frag| IoSliceMut::new(frag)).collect();
+        test_ice.encrypt_vectored(&mut bufs);
+        assert_eq!(frags.concat(), expect);
+        let mut bufs: Vec<IoSliceMut> = frags.iter_mut().map(|frag| IoSliceMut::new(frag)).collect();
+        test_ice.decrypt_vectored(&mut bufs);
+        assert_eq!(frags.concat(), plain);
+    }
+}
+
+#[test]
+fn encrypt_vectored_one_byte_fragments() {
+    use std::io::IoSliceMut;
+
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let plain = EXPECT_TEXT_16.as_bytes();
+    let mut expect = plain.to_vec();
+    test_ice.encrypt(&mut expect);
+
+    let mut bytes: Vec<[u8; 1]> = plain.iter().map(|&byte| [byte]).collect();
+    let mut bufs: Vec<IoSliceMut> = bytes.iter_mut().map(|byte| IoSliceMut::new(byte)).collect();
+    test_ice.encrypt_vectored(&mut bufs);
+    assert_eq!(bytes.concat(), expect);
+}
+
+#[test]
+#[should_panic(expected = "Data must be a multiple of 8 bytes")]
+fn encrypt_vectored_bad_length() {
+    use std::io::IoSliceMut;
+
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mut first = [0u8; 5];
+    let mut second = [0u8; 7];
+    test_ice.encrypt_vectored(&mut [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)]);
+}
+
+static THIN_DECRYPTOR: icefast::ThinIce = icefast::ThinIce::from_const_key(KEY8);
+
+#[test]
+fn thin_ice_const_key_matches_ice() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    // 11 blocks cover the interleaved chunks and the single block remainder
+    let text = EXPECT_TEXT_8.repeat(11);
+    let mut expect = text.as_bytes().to_owned();
+    test_ice.encrypt(&mut expect);
+
+    let mut data = text.as_bytes().to_owned();
+    THIN_DECRYPTOR.encrypt(&mut data);
+    assert_eq!(data, expect);
+    THIN_DECRYPTOR.decrypt(&mut data);
+    assert_eq!(data, text.as_bytes());
+}
+
+#[test]
+fn thin_ice_const_key_random_keys() {
+    let mut state = 0x0123456789abcdef;
+    for _ in 0..100 {
+        let key: [u8; 8] = random_bytes(&mut state, 8).try_into().unwrap();
+        let test_ice = icefast::Ice::new(0, &key);
+        let thin_ice = icefast::ThinIce::from_const_key(key);
+
+        let text = random_bytes(&mut state, 8 * 9);
+        let mut expect = text.clone();
+        test_ice.encrypt(&mut expect);
+        let mut data = text.clone();
+        thin_ice.encrypt(&mut data);
+        assert_eq!(data, expect);
+        thin_ice.decrypt(&mut data);
+        assert_eq!(data, text);
+    }
+}
+
+#[test]
+fn calibration_execution_thresholds() {
+    let calibration = icefast::Calibration {
+        kernel: icefast::Kernel::Scalar,
+        interleaved_min: 64,
+        simd_min: usize::MAX,
+        parallel_min: 1 << 16,
+    };
+    assert_eq!(calibration.execution(8), icefast::Execution::Single);
+    assert_eq!(calibration.execution(64), icefast::Execution::Interleaved);
+    assert_eq!(calibration.execution((1 << 16) - 8), icefast::Execution::Interleaved);
+    assert_eq!(calibration.execution(1 << 16), icefast::Execution::Parallel);
+}
+
+#[test]
+fn calibration_save_load() {
+    let path = std::env::temp_dir().join(format!("ice-calibration-{}", std::process::id()));
+    let calibration = icefast::Calibration {
+        kernel: icefast::Kernel::Bitsliced,
+        interleaved_min: 256,
+        simd_min: 4096,
+        parallel_min: usize::MAX,
+    };
+    calibration.save(&path).unwrap();
+    assert_eq!(icefast::Calibration::load(&path).unwrap(), calibration);
+
+    std::fs::write(&path, "kernel=Scalar\nsimd_min=lots\n").unwrap();
+    assert!(icefast::Calibration::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn encrypt_auto_matches_encrypt() {
+    let calibration = icefast::Calibration::global();
+    assert!(calibration.interleaved_min <= calibration.simd_min);
+    // Too late to install one once measured
+    assert_eq!(calibration.install(), Err(*calibration));
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for blocks in [1, 5, 64, 1003, 40000] {
+        let text = EXPECT_TEXT_8.repeat(blocks);
+        let mut expect = text.as_bytes().to_owned();
+        test_ice.encrypt(&mut expect);
+
+        let mut data = text.as_bytes().to_owned();
+        test_ice.encrypt_auto(&mut data);
+        assert_eq!(data, expect, "{:?}", test_ice.auto_execution(data.len()));
+        test_ice.decrypt_auto(&mut data);
+        assert_eq!(data, text.as_bytes());
+    }
+}
+
+#[test]
+fn encrypt_auto_bitsliced_roundtrip() {
+    let plain = icefast::Ice::new(0, &KEY8);
+    let mut test_ice = plain.clone();
+    test_ice.set_kernel(icefast::Kernel::Bitsliced);
+    for blocks in [1, 64, 40000] {
+        let text = EXPECT_TEXT_8.repeat(blocks);
+        let mut expect = text.as_bytes().to_owned();
+        plain.encrypt(&mut expect);
+
+        let mut data = text.as_bytes().to_owned();
+        test_ice.encrypt_auto(&mut data);
+        assert_eq!(data, expect);
+        test_ice.decrypt_auto(&mut data);
+        assert_eq!(data, text.as_bytes());
+    }
+}
+
+#[test]
+fn par_options_match_encrypt() {
+    let options = [
+        icefast::ParOptions::new(),
+        icefast::ParOptions::new().with_min_len(4096),
+        icefast::ParOptions::new().with_min_len(usize::MAX),
+        icefast::ParOptions::new().with_serial_below(1 << 20),
+        icefast::ParOptions::new().with_backend(std::sync::Arc::new(icefast::Serial)),
+        icefast::ParOptions::new()
+            .with_min_len(100)
+            .with_backend(std::sync::Arc::new(icefast::ScopedThreads::new(3))),
+        icefast::ParOptions::new()
+            .with_min_len(usize::MAX)
+            .with_backend(std::sync::Arc::new(icefast::ScopedThreads::new(3))),
+    ];
+    par_options_roundtrip(&options);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_options_rayon_pool() {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    par_options_roundtrip(&[icefast::ParOptions::new().with_min_len(100).with_pool(pool.into())]);
+}
+
+fn par_options_roundtrip(options: &[icefast::ParOptions]) {
+    let test_ice = icefast::Ice::new(2, &KEY16);
+    let text = EXPECT_TEXT_8.repeat(1003);
+    let mut expect = text.as_bytes().to_owned();
+    test_ice.encrypt(&mut expect);
+
+    for option in options.iter() {
+        let mut data = text.as_bytes().to_owned();
+        test_ice.encrypt_par_with(&mut data, option);
+        assert_eq!(data, expect, "{:?}", option);
+        test_ice.decrypt_par_with(&mut data, option);
+        assert_eq!(data, text.as_bytes(), "{:?}", option);
+    }
+}
+
+#[test]
+fn par_progress_reports_every_step() {
+    use std::sync::{Arc, Mutex};
+
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let text = EXPECT_TEXT_8.repeat(10_000);
+    let mut expect = text.as_bytes().to_owned();
+    test_ice.encrypt(&mut expect);
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let seen = reports.clone();
+    let options = icefast::ProgressOptions::new()
+        .with_par(icefast::ParOptions::new().with_backend(Arc::new(icefast::ScopedThreads::new(3))))
+        .with_segment_len(20_000)
+        .with_step(4096)
+        .with_progress(move |bytes| seen.lock().unwrap().push(bytes));
+    let mut data = text.as_bytes().to_owned();
+    test_ice.encrypt_par_progress(&mut data, &options).unwrap();
+    assert_eq!(data, expect);
+
+    // Workers may report out of order, but there is one call per step
+    // passed, and one for the end
+    let mut reports = reports.lock().unwrap().clone();
+    reports.sort();
+    assert_eq!(reports.len(), data.len() / 4096 + 1);
+    for (step, bytes) in reports.iter().enumerate() {
+        assert!(*bytes >= (step + 1) * 4096 || *bytes == data.len(), "{:?}", reports);
+    }
+    assert_eq!(reports.last(), Some(&data.len()));
+
+    test_ice.decrypt_par_progress(&mut data, &options).unwrap();
+    assert_eq!(data, text.as_bytes());
+
+    // One segment for everything
+    let options = icefast::ProgressOptions::new().with_segment_len(usize::MAX);
+    test_ice.encrypt_par_progress(&mut data, &options).unwrap();
+    assert_eq!(data, expect);
+}
+
+#[test]
+fn par_progress_cancel_between_segments() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let text = EXPECT_TEXT_8.repeat(1000);
+    let mut expect = text.as_bytes().to_owned();
+    test_ice.encrypt(&mut expect);
+
+    // Cancelled from the first progress call, the segment under way still finishes
+    let cancel = icefast::CancelToken::new();
+    let trigger = cancel.clone();
+    let options = icefast::ProgressOptions::new()
+        .with_segment_len(3000)
+        .with_step(1000)
+        .with_progress(move |_| trigger.cancel())
+        .with_cancel(cancel.clone());
+    let mut data = text.as_bytes().to_owned();
+    let err = test_ice.encrypt_par_progress(&mut data, &options).unwrap_err();
+    assert_eq!(err, icefast::Cancelled { processed: 3000 });
+    assert_eq!(data[..3000], expect[..3000]);
+    assert_eq!(data[3000..], text.as_bytes()[3000..]);
+
+    // Already cancelled, nothing is touched
+    let mut rest = data[3000..].to_owned();
+    assert_eq!(
+        test_ice.encrypt_par_progress(&mut rest, &options),
+        Err(icefast::Cancelled { processed: 0 })
+    );
+    assert_eq!(rest, text.as_bytes()[3000..]);
+
+    test_ice.encrypt_par_progress(&mut data[3000..], &icefast::ProgressOptions::new()).unwrap();
+    assert_eq!(data, expect);
+}
+
+#[test]
+fn scoped_threads_short_data() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let backend = std::sync::Arc::new(icefast::ScopedThreads::new(8));
+    let options = icefast::ParOptions::new().with_backend(backend);
+    for blocks in [0, 1, 3, 17] {
+        let text = EXPECT_TEXT_8.repeat(blocks);
+        let mut expect = text.as_bytes().to_owned();
+        test_ice.encrypt(&mut expect);
+        let mut data = text.as_bytes().to_owned();
+        test_ice.encrypt_par_with(&mut data, &options);
+        assert_eq!(data, expect, "{} blocks", blocks);
+    }
+}
+
+#[test]
+fn encrypt_block_many_keys_matches_ice() {
+    let mut state = 0x6a09e667f3bcc908;
+    let block: [u8; 8] = EXPECT_TEXT_8.as_bytes().try_into().unwrap();
+    for level in 0..4 {
+        let keys: Vec<Vec<u8>> = (0..7).map(|_| random_bytes(&mut state, 8 * level.max(1))).collect();
+        let cipher = icefast::encrypt_block_many_keys(level, &keys, &block);
+        assert_eq!(cipher.len(), keys.len());
+        for (key, cipher) in keys.iter().zip(cipher.iter()) {
+            let mut expect = block;
+            icefast::Ice::new(level, key).encrypt(&mut expect);
+            assert_eq!(*cipher, expect, "level {}", level);
+        }
+    }
+    assert_eq!(
+        icefast::encrypt_block_many_keys(0, &[KEY8], &block),
+        [CIPHER_TEXT_8_LEVEL0]
+    );
+}
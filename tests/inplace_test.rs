@@ -0,0 +1,139 @@
+// Testing for memory mapped in-place encryption and its journal
+#![cfg(feature = "mmap")]
+
+mod common;
+
+#[path = "../src/inplace.rs"]
+mod inplace;
+
+use std::path::PathBuf;
+
+use common::{error, icefast, text, KEY8};
+use error::IceError;
+use icefast::ParOptions;
+use inplace::{
+    decrypt_file_in_place, decrypt_file_in_place_with, encrypt_file_in_place,
+    encrypt_file_in_place_with, journal_path, recover_in_place, Direction, InPlaceOptions,
+};
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ice-inplace-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn in_place_roundtrip() {
+    let path = temp_file("roundtrip");
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let plain = text(8 * 123_457);
+    let mut expect = plain.clone();
+    test_ice.encrypt(&mut expect);
+    std::fs::write(&path, &plain).unwrap();
+
+    assert_eq!(
+        encrypt_file_in_place(&test_ice, &path).unwrap(),
+        plain.len() as u64
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), expect);
+    assert!(!journal_path(&path).exists());
+
+    let options = InPlaceOptions::new()
+        .with_segment_len(4096)
+        .with_par(ParOptions::new().with_min_len(512));
+    decrypt_file_in_place_with(&test_ice, &path, &options).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), plain);
+
+    let options = InPlaceOptions::new().with_segment_len(usize::MAX);
+    encrypt_file_in_place_with(&test_ice, &path, &options).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), expect);
+    decrypt_file_in_place(&test_ice, &path).unwrap();
+
+    std::fs::write(&path, &plain[..13]).unwrap();
+    let result = encrypt_file_in_place(&test_ice, &path);
+    assert!(matches!(result, Err(IceError::BlockSize(13))));
+    std::fs::write(&path, []).unwrap();
+    assert_eq!(decrypt_file_in_place(&test_ice, &path).unwrap(), 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+/*
+ * Leave the file and journal the way a crash while encrypting segment
+ * `segment` would, with `done` bytes of that segment already encrypted.
+ */
+fn crash(
+    path: &PathBuf,
+    ice: &icefast::Ice,
+    plain: &[u8],
+    segment_len: usize,
+    segment: usize,
+    done: usize,
+) {
+    let offset = segment * segment_len;
+    let mut file = plain.to_vec();
+    ice.encrypt(&mut file[..offset + done]);
+    std::fs::write(path, &file).unwrap();
+
+    let mut key_check = [0; 8];
+    ice.encrypt(&mut key_check);
+    let mut journal = b"ICEJRNL1".to_vec();
+    journal.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+    journal.extend_from_slice(&key_check);
+    journal.extend_from_slice(&(plain.len() as u64).to_be_bytes());
+    journal.extend_from_slice(&(offset as u64).to_be_bytes());
+    journal.extend_from_slice(&plain[offset..(offset + segment_len).min(plain.len())]);
+    std::fs::write(journal_path(path), journal).unwrap();
+}
+
+#[test]
+fn recover_interrupted() {
+    let path = temp_file("recover");
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let options = InPlaceOptions::new().with_segment_len(64);
+    let plain = text(8 * 50);
+    let mut expect = plain.clone();
+    test_ice.encrypt(&mut expect);
+
+    // Nothing, part and all of the segment done when it stopped
+    for done in [0, 24, 64] {
+        crash(&path, &test_ice, &plain, 64, 2, done);
+        let result = encrypt_file_in_place_with(&test_ice, &path, &options);
+        assert!(matches!(result, Err(IceError::Journal(_))));
+
+        let recovered = recover_in_place(&test_ice, &path, &options).unwrap();
+        assert_eq!(recovered, Some(Direction::Encrypt));
+        assert_eq!(std::fs::read(&path).unwrap(), expect, "{} bytes done", done);
+        assert!(!journal_path(&path).exists());
+    }
+    assert_eq!(recover_in_place(&test_ice, &path, &options).unwrap(), None);
+
+    // The wrong key is caught before it can do any damage
+    crash(&path, &test_ice, &plain, 64, 1, 8);
+    let before = std::fs::read(&path).unwrap();
+    let other_ice = icefast::Ice::new(0, &[0; 8]);
+    let result = recover_in_place(&other_ice, &path, &options);
+    assert!(matches!(result, Err(IceError::BadMac)));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    std::fs::remove_file(journal_path(&path)).unwrap();
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn recover_corrupt_journal() {
+    let path = temp_file("corrupt");
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let options = InPlaceOptions::new().with_segment_len(64);
+    let plain = text(8 * 50);
+
+    // An offset so large the segment end wraps around
+    crash(&path, &test_ice, &plain, 64, 1, 8);
+    let mut journal = std::fs::read(journal_path(&path)).unwrap();
+    journal[32..40].copy_from_slice(&(u64::MAX - 7).to_be_bytes());
+    std::fs::write(journal_path(&path), journal).unwrap();
+    let before = std::fs::read(&path).unwrap();
+    let result = recover_in_place(&test_ice, &path, &options);
+    assert!(matches!(result, Err(IceError::BadHeader)));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    std::fs::remove_file(journal_path(&path)).unwrap();
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,192 @@
+// Testing for patching encrypted files in place
+mod common;
+
+#[path = "../src/stream.rs"]
+#[allow(dead_code)]
+mod stream;
+
+#[path = "../src/patch.rs"]
+mod patch;
+
+use std::io::{Cursor, Read, Write};
+
+use common::{error, icefast, mode, text, IV, KEY8};
+use error::IceError;
+use mode::{Mode, Padding};
+use patch::{affected_range, patch, patch_file};
+use stream::{IceReader, IceWriter};
+
+fn encrypt(ice: &icefast::Ice, mode: Mode, padding: Padding, plain: &[u8]) -> Vec<u8> {
+    let mut writer = IceWriter::new(Vec::new(), ice, mode, padding);
+    writer.write_all(plain).unwrap();
+    writer.finish().unwrap();
+    writer.into_inner()
+}
+
+fn decrypt(ice: &icefast::Ice, mode: Mode, padding: Padding, cipher: &[u8]) -> Vec<u8> {
+    let mut plain = Vec::new();
+    let mut reader = IceReader::new(cipher, ice, mode, padding);
+    reader.read_to_end(&mut plain).unwrap();
+    plain
+}
+
+#[test]
+fn patch_matches_reencrypt() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for mode in [Mode::Ecb, Mode::Ctr { nonce: IV }] {
+        for padding in [Padding::None, Padding::Pkcs7, Padding::Stealing] {
+            let len = match (mode, padding) {
+                (Mode::Ecb, Padding::None) => 8 * 100,
+                _ => 8 * 100 + 5,
+            };
+            let mut plain = text(len);
+            let mut cipher = encrypt(&test_ice, mode, padding, &plain);
+            let cipher_len = cipher.len();
+
+            // Inside a block, across blocks, at the start and at the very end
+            for (offset, patch_len) in [(13, 2), (100, 37), (0, 8), (len - 9, 9), (len - 1, 1)] {
+                let bytes = vec![0xA5; patch_len];
+                let before = cipher.clone();
+                let mut file = Cursor::new(&mut cipher);
+                let range =
+                    patch(&test_ice, &mut file, mode, padding, offset as u64, &bytes).unwrap();
+                plain[offset..offset + patch_len].copy_from_slice(&bytes);
+
+                let msg = format!("{:?} {:?} {}+{}", mode, padding, offset, patch_len);
+                assert_eq!(cipher.len(), cipher_len, "{}", msg);
+                assert_eq!(cipher, encrypt(&test_ice, mode, padding, &plain), "{}", msg);
+                assert_eq!(
+                    range,
+                    affected_range(mode, padding, cipher_len as u64, offset as u64, patch_len)
+                );
+                // Nothing outside the reported range was written
+                let (start, end) = (range.start as usize, range.end as usize);
+                assert_eq!(cipher[..start], before[..start], "{}", msg);
+                assert_eq!(cipher[end..], before[end..], "{}", msg);
+                assert!(end - start <= patch_len + 16 + 7, "{}", msg);
+            }
+            assert_eq!(decrypt(&test_ice, mode, padding, &cipher), plain);
+        }
+    }
+}
+
+#[test]
+fn patch_chained_reports_range() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Cbc { iv: IV };
+    let cipher = encrypt(&test_ice, mode, Padding::Pkcs7, &text(100));
+    let mut patched = cipher.clone();
+    let result = patch(
+        &test_ice,
+        &mut Cursor::new(&mut patched),
+        mode,
+        Padding::Pkcs7,
+        42,
+        &[1],
+    );
+    assert!(matches!(result, Err(IceError::Chained(range)) if range == (40..104)));
+    assert_eq!(patched, cipher);
+}
+
+#[test]
+fn patch_past_end() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let cipher = encrypt(&test_ice, Mode::Ecb, Padding::Pkcs7, &text(20));
+    let mut patched = cipher.clone();
+    let result = patch(
+        &test_ice,
+        &mut Cursor::new(&mut patched),
+        Mode::Ecb,
+        Padding::Pkcs7,
+        18,
+        &[1; 3],
+    );
+    assert!(matches!(result, Err(IceError::Io(_))));
+    assert_eq!(patched, cipher);
+}
+
+#[test]
+fn patch_offset_overflow() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let cipher = encrypt(&test_ice, Mode::Ecb, Padding::Pkcs7, &text(20));
+    let range = affected_range(Mode::Ecb, Padding::Pkcs7, 24, u64::MAX - 2, 8);
+    assert!(range.is_empty());
+
+    for mode in [Mode::Ecb, Mode::Cbc { iv: IV }] {
+        let mut patched = cipher.clone();
+        let result = patch(
+            &test_ice,
+            &mut Cursor::new(&mut patched),
+            mode,
+            Padding::Pkcs7,
+            u64::MAX - 2,
+            &[1; 8],
+        );
+        match result {
+            Err(IceError::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(patched, cipher);
+    }
+}
+
+/*
+ * Fails every call, to show nothing is read or written.
+ */
+struct Untouchable;
+
+impl std::io::Read for Untouchable {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::Other.into())
+    }
+}
+
+impl std::io::Write for Untouchable {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::Other.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Other.into())
+    }
+}
+
+impl std::io::Seek for Untouchable {
+    fn seek(&mut self, _: std::io::SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::ErrorKind::Other.into())
+    }
+}
+
+#[test]
+fn patch_empty_touches_nothing() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for mode in [Mode::Ecb, Mode::Cbc { iv: IV }, Mode::Ctr { nonce: IV }] {
+        for offset in [0, 42, u64::MAX] {
+            let result = patch(&test_ice, &mut Untouchable, mode, Padding::Pkcs7, offset, &[]);
+            assert_eq!(result.unwrap(), offset..offset, "{:?}", mode);
+        }
+    }
+}
+
+#[test]
+fn patch_file_in_place() {
+    let path = std::env::temp_dir().join(format!("ice-patch-{}.enc", std::process::id()));
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Ctr { nonce: IV };
+    let mut plain = text(8 * 10_000 + 3);
+    std::fs::write(&path, encrypt(&test_ice, mode, Padding::None, &plain)).unwrap();
+
+    let range = patch_file(&test_ice, &path, mode, Padding::None, 40_001, b"patched").unwrap();
+    assert_eq!(range, 40_000..40_008);
+    plain[40_001..40_008].copy_from_slice(b"patched");
+    assert_eq!(
+        decrypt(
+            &test_ice,
+            mode,
+            Padding::None,
+            &std::fs::read(&path).unwrap()
+        ),
+        plain
+    );
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,119 @@
+// Testing for the pipelined file and stream encryption
+mod common;
+
+#[path = "../src/pipeline.rs"]
+mod pipeline;
+
+use common::{error, icefast, mode, text, KEY8};
+use error::IceError;
+use mode::{Mode, Padding};
+use pipeline::{decrypt_file, decrypt_stream, encrypt_file, encrypt_stream, PipelineOptions};
+
+#[test]
+fn stream_matches_encrypt() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    let options = PipelineOptions::new()
+        .with_segment_len(64)
+        .with_in_flight(2)
+        .with_workers(3);
+    // Empty, less than a segment, exactly segments and a short last segment
+    for len in [0, 8, 56, 64, 128, 8 * 1001] {
+        let plain = text(len);
+        let mut expect = plain.clone();
+        test_ice.encrypt(&mut expect);
+
+        let mut cipher = Vec::new();
+        let written = encrypt_stream(&test_ice, &plain[..], &mut cipher, Mode::Ecb, &options).unwrap();
+        assert_eq!(written, len as u64);
+        assert_eq!(cipher, expect, "{} bytes", len);
+
+        let mut decrypted = Vec::new();
+        decrypt_stream(&test_ice, &cipher[..], &mut decrypted, Mode::Ecb, &options).unwrap();
+        assert_eq!(decrypted, plain, "{} bytes", len);
+    }
+}
+
+#[test]
+fn stream_ctr_matches_mode() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Ctr {
+        nonce: [0, 0, 0, 1, 0xff, 0xff, 0xff, 0xf0],
+    };
+    let options = PipelineOptions::new()
+        .with_segment_len(64)
+        .with_in_flight(3)
+        .with_workers(2);
+    // CTR doesn't need whole blocks, the last segment may end anywhere
+    for len in [0, 13, 64, 69, 8 * 1001, 8 * 1001 + 3] {
+        let plain = text(len);
+        let expect = mode::encrypt(&test_ice, mode, Padding::None, &plain).unwrap();
+
+        let mut cipher = Vec::new();
+        let written = encrypt_stream(&test_ice, &plain[..], &mut cipher, mode, &options).unwrap();
+        assert_eq!(written, len as u64);
+        assert_eq!(cipher, expect, "{} bytes", len);
+
+        let mut decrypted = Vec::new();
+        decrypt_stream(&test_ice, &cipher[..], &mut decrypted, mode, &options).unwrap();
+        assert_eq!(decrypted, plain, "{} bytes", len);
+    }
+}
+
+#[test]
+fn stream_rejects_cbc() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Cbc { iv: [1; 8] };
+    let plain = text(64);
+    match encrypt_stream(&test_ice, &plain[..], Vec::new(), mode, &PipelineOptions::new()) {
+        Err(IceError::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn stream_bad_length() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let options = PipelineOptions::new().with_segment_len(64);
+    let plain = text(100);
+    let result = encrypt_stream(&test_ice, &plain[..], Vec::new(), Mode::Ecb, &options);
+    assert!(matches!(result, Err(IceError::BlockSize(100))));
+}
+
+#[test]
+fn file_roundtrip() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let src = dir.join(format!("ice-pipeline-{}.plain", id));
+    let enc = dir.join(format!("ice-pipeline-{}.enc", id));
+    let dec = dir.join(format!("ice-pipeline-{}.dec", id));
+
+    let test_ice = icefast::Ice::new(2, &[KEY8, KEY8].concat());
+    let options = PipelineOptions::new().with_segment_len(4096).with_in_flight(3);
+    let plain = text(8 * 12345);
+    std::fs::write(&src, &plain).unwrap();
+
+    assert_eq!(encrypt_file(&test_ice, &src, &enc, Mode::Ecb, &options).unwrap(), plain.len() as u64);
+    let mut expect = plain.clone();
+    test_ice.encrypt(&mut expect);
+    assert_eq!(std::fs::read(&enc).unwrap(), expect);
+
+    decrypt_file(&test_ice, &enc, &dec, Mode::Ecb, &options).unwrap();
+    assert_eq!(std::fs::read(&dec).unwrap(), plain);
+
+    std::fs::write(&src, &plain[..13]).unwrap();
+    assert!(matches!(
+        encrypt_file(&test_ice, &src, &enc, Mode::Ecb, &options),
+        Err(IceError::BlockSize(13))
+    ));
+
+    let mode = Mode::Ctr { nonce: [7; 8] };
+    encrypt_file(&test_ice, &src, &enc, mode, &options).unwrap();
+    let expect = mode::encrypt(&test_ice, mode, Padding::None, &plain[..13]).unwrap();
+    assert_eq!(std::fs::read(&enc).unwrap(), expect);
+    decrypt_file(&test_ice, &enc, &dec, mode, &options).unwrap();
+    assert_eq!(std::fs::read(&dec).unwrap(), plain[..13]);
+
+    for path in [src, enc, dec] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
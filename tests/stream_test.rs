@@ -0,0 +1,460 @@
+// Testing for the std::io adapters and the block modes behind them
+mod common;
+
+#[path = "../src/stream.rs"]
+#[allow(dead_code)]
+mod stream;
+
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use common::{error, icefast, mode, text, IV, KEY8, MODES};
+use error::IceError;
+use mode::{Mode, Padding};
+use stream::{IceReader, IceSeekReader, IceWriter};
+
+fn encrypt_block(ice: &icefast::Ice, block: &[u8]) -> [u8; 8] {
+    let mut block: [u8; 8] = block.try_into().unwrap();
+    ice.encrypt(&mut block);
+    block
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+// Block at a time reference for whole blocks
+fn reference(ice: &icefast::Ice, mode: Mode, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut prev = IV;
+    for (i, block) in data.chunks(8).enumerate() {
+        match mode {
+            Mode::Ecb => out.extend_from_slice(&encrypt_block(ice, block)),
+            Mode::Cbc { .. } => {
+                prev = encrypt_block(ice, &xor(block, &prev));
+                out.extend_from_slice(&prev);
+            }
+            Mode::Ctr { nonce } => {
+                let counter = u64::from_be_bytes(nonce) + i as u64;
+                let stream = encrypt_block(ice, &counter.to_be_bytes());
+                out.extend_from_slice(&xor(block, &stream));
+            }
+        }
+    }
+    out
+}
+
+fn write_all_in_pieces(
+    ice: &icefast::Ice,
+    mode: Mode,
+    padding: Padding,
+    data: &[u8],
+    piece: usize,
+) -> Result<Vec<u8>, IceError> {
+    let mut writer = IceWriter::new(Vec::new(), ice, mode, padding);
+    for chunk in data.chunks(piece.max(1)) {
+        writer.write_all(chunk)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner())
+}
+
+#[test]
+fn writer_matches_reference() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for mode in MODES {
+        for len in [0, 8, 64, 8 * 5000] {
+            let plain = text(len);
+            let expect = reference(&test_ice, mode, &plain);
+            for piece in [1, 7, 64, 100_000] {
+                let cipher = write_all_in_pieces(&test_ice, mode, Padding::None, &plain, piece);
+                assert_eq!(cipher.unwrap(), expect, "{:?} {} bytes", mode, len);
+            }
+        }
+    }
+}
+
+#[test]
+fn writer_ctr_partial_block() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Ctr { nonce: IV };
+    let plain = text(8 * 3000 + 5);
+    let cipher = write_all_in_pieces(&test_ice, mode, Padding::Pkcs7, &plain, 333).unwrap();
+    assert_eq!(cipher, reference(&test_ice, mode, &plain));
+}
+
+#[test]
+fn writer_pkcs7() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for mode in [Mode::Ecb, Mode::Cbc { iv: IV }] {
+        for len in [0, 3, 8, 21] {
+            let plain = text(len);
+            let cipher = write_all_in_pieces(&test_ice, mode, Padding::Pkcs7, &plain, 5).unwrap();
+            let pad = 8 - len % 8;
+            let mut padded = plain.clone();
+            padded.resize(len + pad, pad as u8);
+            assert_eq!(
+                cipher,
+                reference(&test_ice, mode, &padded),
+                "{:?} {} bytes",
+                mode,
+                len
+            );
+        }
+    }
+}
+
+#[test]
+fn writer_stealing() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for mode in [Mode::Ecb, Mode::Cbc { iv: IV }] {
+        // Whole blocks are left alone
+        let plain = text(24);
+        let cipher = write_all_in_pieces(&test_ice, mode, Padding::Stealing, &plain, 5).unwrap();
+        assert_eq!(cipher, reference(&test_ice, mode, &plain));
+
+        for len in [9, 21, 8 * 3000 + 3] {
+            let plain = text(len);
+            let cipher =
+                write_all_in_pieces(&test_ice, mode, Padding::Stealing, &plain, 1000).unwrap();
+            assert_eq!(cipher.len(), len);
+
+            // Everything before the last two blocks is plain chaining
+            let m = len % 8;
+            let head = len - 8 - m;
+            let full = reference(&test_ice, mode, &plain[..head + 8]);
+            assert_eq!(cipher[..head], full[..head]);
+
+            // The short block is the head of the last full block's ciphertext
+            let stolen = &full[head..];
+            assert_eq!(cipher[head + 8..], stolen[..m]);
+
+            // and the block before it encrypts the partial block filled from its tail
+            let mut last = plain[head + 8..].to_vec();
+            let last = match mode {
+                Mode::Cbc { .. } => {
+                    last.resize(8, 0);
+                    xor(&last, stolen)
+                }
+                _ => {
+                    last.extend_from_slice(&stolen[m..]);
+                    last
+                }
+            };
+            assert_eq!(cipher[head..head + 8], encrypt_block(&test_ice, &last));
+        }
+    }
+}
+
+#[test]
+fn writer_bad_length() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let result = write_all_in_pieces(&test_ice, Mode::Ecb, Padding::None, &text(13), 4);
+    assert!(matches!(result, Err(IceError::BlockSize(13))));
+    let result = write_all_in_pieces(&test_ice, Mode::Ecb, Padding::Stealing, &text(5), 4);
+    assert!(matches!(result, Err(IceError::BlockSize(5))));
+
+    // The held back bytes are still there for the next try
+    let mut writer = IceWriter::new(Vec::new(), &test_ice, Mode::Ecb, Padding::None);
+    writer.write_all(&text(13)).unwrap();
+    assert!(matches!(writer.finish(), Err(IceError::BlockSize(13))));
+    assert!(matches!(writer.finish(), Err(IceError::BlockSize(13))));
+}
+
+/*
+ * Takes at most `chunk` bytes per write and fails every third one with
+ * WouldBlock, without taking anything.
+ */
+struct Flaky {
+    data: Vec<u8>,
+    calls: usize,
+    chunk: usize,
+}
+
+impl Write for Flaky {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.calls += 1;
+        if self.calls.is_multiple_of(3) {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        let len = buf.len().min(self.chunk);
+        self.data.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writer_retries_after_would_block() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for mode in MODES {
+        let plain = text(8 * 5000 + 3);
+        let inner = Flaky {
+            data: Vec::new(),
+            calls: 0,
+            chunk: 5000,
+        };
+        let mut writer = IceWriter::new(inner, &test_ice, mode, Padding::Pkcs7);
+
+        // An error means nothing was taken, try the same bytes again
+        let mut pos = 0;
+        let mut flushed = false;
+        while pos < plain.len() {
+            let end = (pos + 7000).min(plain.len());
+            match writer.write(&plain[pos..end]) {
+                Ok(len) => pos += len,
+                Err(err) => assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock),
+            }
+            // Halfway through, so there is more to come after the flush
+            if pos >= plain.len() / 2 && !flushed {
+                while let Err(err) = writer.flush() {
+                    assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+                }
+                flushed = true;
+            }
+        }
+
+        // The rest goes out over several calls, some of which fail
+        let mut retries = 0;
+        while let Err(err) = writer.finish() {
+            assert!(matches!(&err, IceError::Io(err) if err.kind() == std::io::ErrorKind::WouldBlock));
+            retries += 1;
+        }
+        assert!(retries > 0, "{:?}", mode);
+        writer.finish().unwrap();
+        let cipher = writer.into_inner().data;
+
+        let mut reader = IceReader::new(&cipher[..], &test_ice, mode, Padding::Pkcs7);
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, plain, "{:?}", mode);
+    }
+}
+
+/*
+ * Reads in uneven pieces so the lookahead gets split across calls.
+ */
+fn read_in_pieces(
+    ice: &icefast::Ice,
+    mode: Mode,
+    padding: Padding,
+    cipher: &[u8],
+    piece: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = IceReader::new(cipher, ice, mode, padding);
+    let mut plain = Vec::new();
+    let mut buf = vec![0; piece];
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            return Ok(plain);
+        }
+        plain.extend_from_slice(&buf[..len]);
+    }
+}
+
+fn ice_error(err: &std::io::Error) -> &IceError {
+    err.get_ref().unwrap().downcast_ref::<IceError>().unwrap()
+}
+
+#[test]
+fn reader_roundtrip() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for mode in MODES {
+        for padding in [Padding::None, Padding::Pkcs7, Padding::Stealing] {
+            for len in [0usize, 8, 9, 21, 64, 8 * 5000, 8 * 5000 + 7] {
+                if padding == Padding::None
+                    && !len.is_multiple_of(8)
+                    && !matches!(mode, Mode::Ctr { .. })
+                {
+                    continue;
+                }
+                if padding == Padding::Stealing && len > 0 && len < 8 {
+                    continue;
+                }
+                let plain = text(len);
+                let cipher = write_all_in_pieces(&test_ice, mode, padding, &plain, 1000).unwrap();
+                for piece in [1, 7, 4096, 100_000] {
+                    let decrypted = read_in_pieces(&test_ice, mode, padding, &cipher, piece);
+                    let msg = format!("{:?} {:?} {} bytes", mode, padding, len);
+                    assert_eq!(decrypted.expect(&msg), plain, "{}", msg);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn one_shot_matches_adapters() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for mode in MODES {
+        for padding in [Padding::Pkcs7, Padding::Stealing] {
+            let plain = text(8 * 3000 + 5);
+            let cipher = mode::encrypt(&test_ice, mode, padding, &plain).unwrap();
+            assert_eq!(
+                cipher,
+                write_all_in_pieces(&test_ice, mode, padding, &plain, 999).unwrap()
+            );
+            assert_eq!(
+                mode::decrypt(&test_ice, mode, padding, &cipher).unwrap(),
+                plain
+            );
+        }
+    }
+}
+
+#[test]
+fn reader_read_to_end() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Cbc { iv: IV };
+    let plain = text(100_003);
+    let cipher = write_all_in_pieces(&test_ice, mode, Padding::Pkcs7, &plain, 4096).unwrap();
+    let mut reader = IceReader::new(&cipher[..], &test_ice, mode, Padding::Pkcs7);
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted).unwrap();
+    assert_eq!(decrypted, plain);
+    assert!(reader.into_inner().is_empty());
+}
+
+#[test]
+fn reader_bad_padding() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let other_ice = icefast::Ice::new(0, &[0; 8]);
+    let mode = Mode::Cbc { iv: IV };
+    let cipher = write_all_in_pieces(&test_ice, mode, Padding::Pkcs7, &text(29), 5).unwrap();
+
+    // A wrong key garbles the padding
+    let err = read_in_pieces(&other_ice, mode, Padding::Pkcs7, &cipher, 64).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matches!(ice_error(&err), IceError::BadPadding));
+
+    // So does a flipped bit in the block before, through the chaining
+    let mut tampered = cipher.clone();
+    let last = tampered.len() - 9;
+    tampered[last] ^= 1;
+    let err = read_in_pieces(&test_ice, mode, Padding::Pkcs7, &tampered, 64).unwrap_err();
+    assert!(matches!(ice_error(&err), IceError::BadPadding));
+
+    let err = read_in_pieces(&test_ice, mode, Padding::Pkcs7, &[], 64).unwrap_err();
+    assert!(matches!(ice_error(&err), IceError::BadPadding));
+}
+
+#[test]
+fn reader_bad_length() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    for padding in [Padding::None, Padding::Pkcs7] {
+        let err = read_in_pieces(&test_ice, Mode::Ecb, padding, &text(13), 4).unwrap_err();
+        assert!(matches!(ice_error(&err), IceError::BlockSize(13)));
+    }
+    let err = read_in_pieces(&test_ice, Mode::Ecb, Padding::Stealing, &text(5), 4).unwrap_err();
+    assert!(matches!(ice_error(&err), IceError::BlockSize(5)));
+}
+
+/*
+ * Counts the ciphertext bytes pulled through, to check seeks don't read
+ * more than they need.
+ */
+struct Counting<R> {
+    inner: R,
+    read: usize,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.read += len;
+        Ok(len)
+    }
+}
+
+impl<R: Seek> Seek for Counting<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn seek_reader_random_access() {
+    let test_ice = icefast::Ice::new(1, &KEY8);
+    for mode in MODES {
+        for padding in [Padding::None, Padding::Pkcs7, Padding::Stealing] {
+            let len = match (mode, padding) {
+                (Mode::Ecb | Mode::Cbc { .. }, Padding::None) => 8 * 5000,
+                _ => 8 * 5000 + 3,
+            };
+            let plain = text(len);
+            let cipher = write_all_in_pieces(&test_ice, mode, padding, &plain, 4096).unwrap();
+            let mut reader =
+                IceSeekReader::new(Cursor::new(&cipher), &test_ice, mode, padding).unwrap();
+            assert_eq!(reader.len(), len as u64);
+
+            // Back and forth, across run and tail boundaries and past the end
+            let reads = [
+                (len - 3, 3),
+                (len - 11, 20),
+                (16 * 1024 - 5, 10),
+                (3, 16 * 1024 + 9),
+                (0, 1),
+                (20_000, 7),
+                (len - 16, 16),
+                (len + 5, 4),
+            ];
+            for (start, want) in reads {
+                let mut buf = vec![0; want];
+                reader.seek(SeekFrom::Start(start as u64)).unwrap();
+                let got = reader.read(&mut buf).unwrap();
+                let expect = &plain[start.min(len)..(start + want).min(len)];
+                let msg = format!("{:?} {:?} at {}", mode, padding, start);
+                assert!(got > 0 || expect.is_empty(), "{}", msg);
+                assert_eq!(buf[..got], expect[..got], "{}", msg);
+            }
+
+            let mut end = Vec::new();
+            reader.seek(SeekFrom::End(-21)).unwrap();
+            reader.read_to_end(&mut end).unwrap();
+            assert_eq!(end, plain[len - 21..]);
+        }
+    }
+}
+
+#[test]
+fn seek_reader_reads_only_touched_blocks() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let plain = text(8 * 100_000);
+    for mode in MODES {
+        let cipher = write_all_in_pieces(&test_ice, mode, Padding::Pkcs7, &plain, 4096).unwrap();
+        let inner = Counting {
+            inner: Cursor::new(&cipher),
+            read: 0,
+        };
+        let mut reader = IceSeekReader::new(inner, &test_ice, mode, Padding::Pkcs7).unwrap();
+        let setup = reader.get_ref().read;
+
+        // 10 bytes over two blocks, plus the block before for CBC
+        let mut buf = [0; 10];
+        reader.seek(SeekFrom::Start(400_004)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, plain[400_004..400_014]);
+        assert!(reader.get_ref().read - setup <= 24, "{:?}", mode);
+    }
+}
+
+#[test]
+fn seek_reader_errors() {
+    let test_ice = icefast::Ice::new(0, &KEY8);
+    let mode = Mode::Cbc { iv: IV };
+    let mut cipher = write_all_in_pieces(&test_ice, mode, Padding::Pkcs7, &text(40), 8).unwrap();
+
+    let mut reader =
+        IceSeekReader::new(Cursor::new(&cipher), &test_ice, mode, Padding::Pkcs7).unwrap();
+    assert!(reader.seek(SeekFrom::Current(-1)).is_err());
+
+    let last = cipher.len() - 1;
+    cipher[last] ^= 1;
+    let result = IceSeekReader::new(Cursor::new(&cipher), &test_ice, mode, Padding::Pkcs7);
+    assert!(matches!(result, Err(IceError::BadPadding)));
+    let result = IceSeekReader::new(Cursor::new(&cipher[..13]), &test_ice, mode, Padding::None);
+    assert!(matches!(result, Err(IceError::BlockSize(13))));
+}